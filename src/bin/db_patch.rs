@@ -0,0 +1,70 @@
+//! DB 差分补丁工具，供服务器运维生成补丁
+//!
+//! 生成：`db_patch diff <旧DB> <新DB> <补丁文件>`
+//! 应用：`db_patch apply <旧DB> <补丁文件> <输出文件>`
+//!
+//! 补丁放在下载域名的 `patches/` 目录下，文件名为 `{旧DB摘要}_{新DB摘要}.patch`，
+//! 摘要为十六进制、不带算法前缀，算法与令牌 `dh` 字段一致。
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::ExitCode;
+
+#[path = "../db_patch.rs"]
+mod db_patch;
+#[cfg(test)]
+#[allow(dead_code)]
+#[path = "../test_util.rs"]
+mod test_util;
+
+fn usage() -> ExitCode {
+    eprintln!("用法:");
+    eprintln!("  db_patch diff <旧DB> <新DB> <补丁文件>");
+    eprintln!("  db_patch apply <旧DB> <补丁文件> <输出文件>");
+    ExitCode::FAILURE
+}
+
+fn diff(old: &Path, new: &Path, patch: &Path) -> std::io::Result<()> {
+    let literal = db_patch::diff(old, new, patch)?;
+    println!(
+        "补丁已生成 {}，新数据 {} bytes，补丁大小 {} bytes",
+        patch.display(),
+        literal,
+        fs::metadata(patch)?.len()
+    );
+
+    // 应用一次补丁，确认能还原出新文件
+    let check = patch.with_extension("check");
+    db_patch::apply(old, patch, &check)?;
+    let same = fs::read(&check)? == fs::read(new)?;
+    fs::remove_file(&check)?;
+    if !same {
+        return Err(std::io::Error::other(
+            "补丁校验失败，还原结果与新文件不一致",
+        ));
+    }
+    println!("补丁校验通过");
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let res = match args.as_slice() {
+        [cmd, old, new, patch] if cmd == "diff" => {
+            diff(Path::new(old), Path::new(new), Path::new(patch))
+        }
+        [cmd, old, patch, out] if cmd == "apply" => {
+            db_patch::apply(Path::new(old), Path::new(patch), Path::new(out))
+        }
+        _ => return usage(),
+    };
+
+    match res {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("失败: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,85 @@
+//! 更新清单签名工具，供服务器运维使用
+//!
+//! 生成密钥：`sign-manifest keygen <私钥文件>`，构建启动器时把输出的公钥设置到环境变量 `PLAA_MANIFEST_PUBLIC_KEY`
+//! 签名：`sign-manifest sign <私钥文件> <清单文件>`，生成 `<清单文件>.sig`
+//! 验证：`sign-manifest verify <公钥> <清单文件>`
+//!
+//! 清单放在下载域名下：`update_manifest.json` 列出 DB 文件，`client_manifest.json` 列出客户端文件，
+//! 签名文件与清单放在同一目录。清单修改后必须重新签名。
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+#[path = "../signature.rs"]
+mod signature;
+
+fn usage() -> ExitCode {
+    eprintln!("用法:");
+    eprintln!("  sign-manifest keygen <私钥文件>");
+    eprintln!("  sign-manifest sign <私钥文件> <清单文件>");
+    eprintln!("  sign-manifest verify <公钥> <清单文件>");
+    ExitCode::FAILURE
+}
+
+fn sig_path(manifest: &Path) -> PathBuf {
+    let mut path = manifest.as_os_str().to_owned();
+    path.push(signature::SUFFIX);
+    PathBuf::from(path)
+}
+
+fn keygen(secret: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    if secret.exists() {
+        return Err(format!("{} 已存在，不会覆盖", secret.display()).into());
+    }
+    let secret_hex = hex::encode(rand::random::<[u8; 32]>());
+    fs::write(secret, &secret_hex)?;
+    println!("私钥已保存到 {}，请妥善保管", secret.display());
+    println!("公钥: {}", signature::public_key(&secret_hex)?);
+    Ok(())
+}
+
+fn sign(secret: &Path, manifest: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let secret_hex = fs::read_to_string(secret)?;
+    let data = fs::read(manifest)?;
+
+    // 签名前确认清单格式正确
+    let value: serde_json::Value = serde_json::from_slice(&data)?;
+    if !value.get("files").is_some_and(|f| f.is_array()) {
+        return Err("清单缺少 files 数组".into());
+    }
+
+    let sig = signature::sign(&secret_hex, &data)?;
+    signature::verify(&signature::public_key(&secret_hex)?, &data, &sig)?;
+    let path = sig_path(manifest);
+    fs::write(&path, sig)?;
+    println!("签名已保存到 {}", path.display());
+    Ok(())
+}
+
+fn verify(public_hex: &str, manifest: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let data = fs::read(manifest)?;
+    let sig = fs::read_to_string(sig_path(manifest))?;
+    signature::verify(public_hex, &data, &sig)?;
+    println!("签名有效");
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let res = match args.as_slice() {
+        [cmd, secret] if cmd == "keygen" => keygen(Path::new(secret)),
+        [cmd, secret, manifest] if cmd == "sign" => sign(Path::new(secret), Path::new(manifest)),
+        [cmd, public, manifest] if cmd == "verify" => verify(public, Path::new(manifest)),
+        _ => return usage(),
+    };
+
+    match res {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("失败: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
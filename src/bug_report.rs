@@ -0,0 +1,228 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{diagnostics, helper, http, launcher_config, redact, VERSION};
+
+/// 提交失败的反馈保存为 JSON，下次启动时重新提交
+const QUEUE_EXT: &str = "json";
+
+/// 问题描述的最大长度（字符）
+const MAX_DESCRIPTION_LEN: usize = 4000;
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Report {
+    launcher_version: u16,
+    time: u64,
+    description: String,
+    /// 脱敏后的诊断摘要
+    summary: String,
+}
+
+pub fn dir() -> PathBuf {
+    helper::data_dir().join("reports")
+}
+
+fn url(domain: &str) -> String {
+    let path = launcher_config::load().bug_report_path;
+    format!("{}/{}", domain, path.trim_start_matches('/'))
+}
+
+async fn send(url: &str, report: &Report) -> Result<(), reqwest::Error> {
+    info!("提交问题反馈 {}", url);
+    http::client()
+        .post(url)
+        .json(report)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+fn enqueue(dir: &Path, report: &Report) -> std::io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let path = dir.join(format!(
+        "report-{}-{:08x}.{}",
+        report.time,
+        rand::random::<u32>(),
+        QUEUE_EXT
+    ));
+    fs::write(&path, serde_json::to_vec(report)?)?;
+    Ok(path)
+}
+
+/// 提交问题反馈，附带脱敏后的诊断摘要；失败时保存到本地，下次启动时重新提交
+pub async fn submit(
+    domain: &str,
+    description: &str,
+    expected_hash: Option<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let description: String = description
+        .trim()
+        .chars()
+        .take(MAX_DESCRIPTION_LEN)
+        .collect();
+    if description.is_empty() {
+        return Err("请填写问题描述".into());
+    }
+    let summary =
+        tokio::task::spawn_blocking(move || diagnostics::summary(expected_hash.as_deref())).await?;
+    let report = Report {
+        launcher_version: VERSION,
+        time: helper::timestamp(),
+        description: redact::redact(&description).into_owned(),
+        summary,
+    };
+    submit_to(&url(domain), &dir(), &report).await
+}
+
+/// 提交到 `url`，失败时保存到 `queue` 目录
+async fn submit_to(
+    url: &str,
+    queue: &Path,
+    report: &Report,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Err(e) = send(url, report).await {
+        warn!("提交问题反馈失败 {:?}", e);
+        let path = enqueue(queue, report)?;
+        info!("问题反馈已保存 {:?}", path);
+        return Err(format!("网络异常，已保存，下次启动时自动提交\n{}", e).into());
+    }
+    Ok(())
+}
+
+/// 重新提交之前失败的问题反馈，遇到网络错误时停止，留到下次；
+/// 服务器拒绝的反馈（4xx）重试也不会成功，删除后继续提交下一个
+pub async fn resend(domain: &str) {
+    resend_from(&url(domain), &dir()).await
+}
+
+async fn resend_from(url: &str, queue: &Path) {
+    let Ok(entries) = fs::read_dir(queue) else {
+        return;
+    };
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == QUEUE_EXT))
+        .collect();
+    files.sort();
+
+    for path in files {
+        let report = match fs::read(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|data| serde_json::from_slice::<Report>(&data).map_err(|e| e.to_string()))
+        {
+            Ok(report) => report,
+            Err(e) => {
+                warn!("问题反馈文件无效，已删除 {:?} {}", path, e);
+                let _ = fs::remove_file(&path);
+                continue;
+            }
+        };
+        match send(url, &report).await {
+            Err(e) if http::is_transient(&e) => {
+                warn!("重新提交问题反馈失败 {:?}", e);
+                return;
+            }
+            Err(e) => {
+                warn!("问题反馈被服务器拒绝，已删除 {:?} {:?}", path, e);
+                let _ = fs::remove_file(&path);
+                continue;
+            }
+            Ok(()) => {}
+        }
+        info!("已重新提交问题反馈 {:?}", path);
+        let _ = fs::remove_file(&path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::stub::{response, serve};
+    use crate::test_util::temp_dir;
+
+    fn queued(dir: &Path) -> usize {
+        fs::read_dir(dir).map_or(0, |entries| entries.count())
+    }
+
+    fn report(description: &str) -> Report {
+        Report {
+            launcher_version: VERSION,
+            time: helper::timestamp(),
+            description: description.to_string(),
+            summary: "summary".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn submit_success() {
+        let stub = serve(vec![response("200 OK", &[], b"")]);
+        let dir = temp_dir("bug-report-success");
+
+        submit_to(&stub.url, &dir, &report("game crashed"))
+            .await
+            .unwrap();
+        let requests = stub.requests();
+        assert!(requests[0].starts_with("post / "));
+        assert!(requests[0].contains("game crashed"));
+        assert_eq!(queued(&dir), 0);
+    }
+
+    #[tokio::test]
+    async fn enqueue_on_failure() {
+        let stub = serve(vec![response("503 Service Unavailable", &[], b"")]);
+        let dir = temp_dir("bug-report-failure");
+
+        assert!(submit_to(&stub.url, &dir, &report("first")).await.is_err());
+        assert_eq!(queued(&dir), 1);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn resend_drains_queue() {
+        let dir = temp_dir("bug-report-resend");
+        for description in ["first", "second", "third"] {
+            enqueue(&dir, &report(description)).unwrap();
+        }
+        fs::write(dir.join("broken.json"), b"not json").unwrap();
+
+        // 第二次提交失败时停止，剩下的留到下次
+        let stub = serve(vec![
+            response("200 OK", &[], b""),
+            response("503 Service Unavailable", &[], b""),
+        ]);
+        resend_from(&stub.url, &dir).await;
+        assert_eq!(stub.requests().len(), 2);
+        assert_eq!(queued(&dir), 2);
+
+        let stub = serve(vec![
+            response("200 OK", &[], b""),
+            response("200 OK", &[], b""),
+        ]);
+        resend_from(&stub.url, &dir).await;
+        assert_eq!(stub.requests().len(), 2);
+        assert_eq!(queued(&dir), 0);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn resend_drops_rejected_reports() {
+        let dir = temp_dir("bug-report-rejected");
+        for description in ["first", "second"] {
+            enqueue(&dir, &report(description)).unwrap();
+        }
+
+        // 被拒绝的反馈删除后继续提交下一个
+        let stub = serve(vec![
+            response("400 Bad Request", &[], b""),
+            response("200 OK", &[], b""),
+        ]);
+        resend_from(&stub.url, &dir).await;
+        assert_eq!(stub.requests().len(), 2);
+        assert_eq!(queued(&dir), 0);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -1,64 +1,122 @@
+use crate::db_check::ExpectedDigest;
+use crate::events::{Event, EventBus};
 use crate::protocol::AuthToken;
 use crate::{
-    db_check, protocol, regedit, site_link_url, system_config, trion_1_2, uac, MainWindow,
-    MessageActions, State, SENDER, VERSION,
+    bug_report, crash_report, db_check, download_meta, game_check, helper, lan_peer,
+    launcher_config, mirror, protocol, regedit, site_link_url, system_config, trion_1_2,
+    trusted_domain, uac, MainWindow, MessageActions, State, VERSION,
 };
-use tracing::error;
+use std::path::Path;
+use tracing::{error, info, warn};
 
-pub async fn handle(window: &MainWindow) {
+/// 记录崩溃报告中的阶段并通知订阅者
+fn set_stage(events: &EventBus, stage: &'static str) {
+    crash_report::set_stage(stage);
+    events.publish(Event::Stage(stage));
+}
+
+/// 启动失败：切换到失败状态，日志、诊断和反馈按钮仍然可用，
+/// 再发送 `Event::Message`，界面订阅者在流程结束后显示，日志和控制台也会收到
+fn fail(
+    window: &MainWindow,
+    events: &EventBus,
+    title: &str,
+    content: String,
+    action: MessageActions,
+) {
+    window.invoke_changeState(State::Failed);
+    events.publish(Event::Message(title.to_string(), content, action));
+}
+
+/// 启动流程，失败时见 [`fail`]
+pub async fn handle(window: &MainWindow, events: &EventBus) {
+    // 最先检查，后面的步骤失败时也能提交崩溃报告
+    if crash_report::pending().is_some() {
+        window.invoke_showCrashReport();
+    }
+
+    set_stage(events, "注册协议");
     if !regedit::detecting() {
         uac::handle();
 
         if !regedit::register() {
-            window.invoke_message(
-                "注册表".into(),
-                "写入注册表失败".into(),
+            fail(
+                window,
+                events,
+                "注册表",
+                "写入注册表失败".to_string(),
                 MessageActions::Exit,
             );
             return;
         }
     }
 
+    set_stage(events, "解析令牌");
     let res = protocol::handle().await;
 
     if res.is_err() {
-        window.invoke_message(
-            "启动器".into(),
-            "请通过官网启动".into(),
+        fail(
+            window,
+            events,
+            "启动器",
+            "请通过官网启动".to_string(),
             MessageActions::Exit,
         );
         return;
     }
     let auth_token = res.unwrap();
 
+    if let Err(e) = trusted_domain::check(&auth_token.domain) {
+        error!("令牌域名不可信 {}", e);
+        fail(
+            window,
+            events,
+            "启动器",
+            format!("{}\n请确认是否从官网启动", e),
+            MessageActions::Exit,
+        );
+        return;
+    }
+
+    let domain = auth_token.domain.clone();
+    tokio::spawn(async move { bug_report::resend(&domain).await });
+
+    set_stage(events, "创建快捷方式");
     let _ = site_link_url::handle(&auth_token.domain).await;
 
+    set_stage(events, "检查版本");
     if !handle_version(auth_token.with_launcher_version).await {
-        window.invoke_message(
-            "启动器".into(),
-            "当前本本过低，请安装最新版本".into(),
+        fail(
+            window,
+            events,
+            "启动器",
+            "当前本本过低，请安装最新版本".to_string(),
             MessageActions::Exit,
         );
         return;
     }
 
-    if !trion_1_2::handle_bin_check().await {
-        error!("找不到游戏程序，请将启动器放置在游戏目录。和 game_pak 文件同目录。");
-        if let Some(tx) = SENDER.get() {
-            tx.lock()
-                .await
-                .send(crate::Task::Message(
-                    String::from("系统错误"),
-                    String::from("未检测到游戏"),
-                    MessageActions::Exit,
-                ))
-                .await
-                .ok();
-        };
+    set_stage(events, "检查游戏目录");
+    let report = game_check::validate(&helper::game_root());
+    if !report.is_ok() {
+        error!("游戏目录校验失败 {}", report);
+        fail(
+            window,
+            events,
+            "未检测到游戏",
+            format!("{}\n请选择游戏目录", report),
+            MessageActions::PickGameDir,
+        );
         return;
     }
+    if !report.missing_dlls.is_empty() {
+        warn!("{}", report);
+    }
 
-    if !handle_db_check(&auth_token).await {
+    lan_peer::start();
+
+    set_stage(events, "校验DB");
+    if !handle_db_check(&auth_token, false).await {
         // window.invoke_message(
         //     "DB文件校验".into(),
         //     "数值不正确".into(),
@@ -67,8 +125,10 @@ pub async fn handle(window: &MainWindow) {
         window.invoke_changeState(State::Upgrade);
         return;
     }
+    set_stage(events, "更新系统配置");
     handle_conf().await;
 
+    set_stage(events, "就绪");
     window.invoke_changeState(State::Ready);
     // web_site::open_website(WEBSITE_URL);
 
@@ -79,11 +139,26 @@ pub async fn handle_conf() {
     let _ = system_config::update().await;
 }
 
-pub async fn handle_db_check(auth_token: &AuthToken) -> bool {
-    if db_check::detect_db(auth_token.db_hash.as_ref()).is_err() {
-        return false;
+/// 在后台线程中校验 DB，避免阻塞界面
+pub async fn handle_db_check(auth_token: &AuthToken, force: bool) -> bool {
+    if auth_token.db_hash.is_empty() {
+        // 令牌没有摘要时询问服务器文件是否变化
+        let mirrors = mirror::list(auth_token).await;
+        let check = download_meta::check(&mirrors, &auth_token.db_file, &db_check::db_path()).await;
+        return matches!(check, download_meta::Check::NotModified);
+    }
+
+    let hash = auth_token.db_hash.clone();
+    let ok = tokio::task::spawn_blocking(move || db_check::detect_db(&hash, force).is_ok())
+        .await
+        .unwrap_or(false);
+    if ok {
+        if let Ok(expected) = ExpectedDigest::parse(&auth_token.db_hash) {
+            let db_path = db_check::db_path();
+            lan_peer::share(&auth_token.db_file, Path::new(&db_path), &expected);
+        }
     }
-    true
+    ok
 }
 
 pub async fn handle_version(with_launcher_version: u16) -> bool {
@@ -94,6 +169,19 @@ pub async fn handle_version(with_launcher_version: u16) -> bool {
     }
 }
 
-pub async fn handle_launch(auth_token: &AuthToken) {
-    trion_1_2::launch(auth_token).await;
+pub async fn handle_launch(auth_token: AuthToken, events: &EventBus) {
+    trion_1_2::launch(auth_token, events).await;
+}
+
+/// 校验用户选择的目录，通过后记住该目录
+pub fn handle_pick_game_dir(path: &Path) -> Result<(), String> {
+    let report = game_check::validate(path);
+    if !report.is_ok() {
+        return Err(report.to_string());
+    }
+    info!("使用游戏目录 {:?}", path);
+    launcher_config::update(|config| {
+        config.game_path = Some(path.to_string_lossy().to_string());
+    })
+    .map_err(|e| e.to_string())
 }
@@ -1,12 +1,16 @@
 use std::str;
 use rc4::{KeyInit, Rc4, StreamCipher};
+use zeroize::Zeroizing;
 
-// 使用 AES-128-CBC 解密
-pub fn decrypt(plain_data: &[u8], encryption_key: &[u8; 8]) -> Result<Vec<u8>, &'static str> {
+// 使用 AES-128-CBC 解密，明文包含密码，释放时清零
+pub fn decrypt(
+    plain_data: &[u8],
+    encryption_key: &[u8; 8],
+) -> Result<Zeroizing<Vec<u8>>, &'static str> {
     let mut rc4 = Rc4::new(encryption_key.into());
 
     // 创建 `plain_data` 的一个可变副本
-    let mut data = plain_data.to_vec(); // 将不可变切片转换为可变的 Vec<u8>
+    let mut data = Zeroizing::new(plain_data.to_vec()); // 将不可变切片转换为可变的 Vec<u8>
 
     rc4.apply_keystream(&mut data);
     Ok(data)
@@ -0,0 +1,160 @@
+use std::path::{Component, Path, PathBuf};
+
+use tracing::{info, warn};
+
+use crate::db_check::ExpectedDigest;
+use crate::download_control::Cancelled;
+use crate::events::{Event, EventBus};
+use crate::manifest::{Manifest, ManifestFile};
+use crate::progress::Tracker;
+use crate::protocol::AuthToken;
+use crate::{
+    db_check, disk_space, download, download_control, helper, manifest, mirror, trusted_domain,
+};
+
+const MANIFEST_NAME: &str = "client_manifest.json";
+
+/// 校验结果
+#[derive(Debug, Default)]
+pub struct ScanReport {
+    pub missing: Vec<ManifestFile>,
+    pub modified: Vec<ManifestFile>,
+}
+
+impl ScanReport {
+    pub fn broken(&self) -> impl Iterator<Item = &ManifestFile> {
+        self.missing.iter().chain(self.modified.iter())
+    }
+}
+
+/// 清单中的路径只允许指向游戏目录内部
+fn local_path(root: &Path, file: &ManifestFile) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let relative = Path::new(&file.path);
+    if !relative
+        .components()
+        .all(|c| matches!(c, Component::Normal(_)))
+    {
+        return Err(format!("清单路径无效: {}", file.path).into());
+    }
+    Ok(root.join(relative))
+}
+
+/// 在后台线程中完整计算文件摘要并与清单比较。
+/// 客户端文件很多，不经过摘要缓存，避免每个文件都重写一次缓存文件。
+/// 大文件计算时间较长，取消时不等待计算结束
+async fn verify(path: &Path, hash: &str) -> Result<bool, Box<dyn std::error::Error>> {
+    let expected = ExpectedDigest::parse(hash)?;
+    let algorithm = expected.algorithm;
+    let path = path.to_path_buf();
+    let task = tokio::task::spawn_blocking(move || {
+        db_check::hash_file(&path, algorithm).map_err(|e| e.to_string())
+    });
+    let hex = tokio::select! {
+        res = task => res??,
+        _ = download_control::controller().wait_cancel() => return Err(Cancelled.into()),
+    };
+    Ok(hex == expected.hex)
+}
+
+/// 获取客户端清单，签名无效时不做任何修改
+pub async fn fetch_manifest(domain: &str) -> Result<Manifest, Box<dyn std::error::Error>> {
+    manifest::fetch(domain, MANIFEST_NAME).await
+}
+
+/// 逐个计算清单中文件的哈希，进度显示在升级进度条上
+pub async fn scan(
+    root: &Path,
+    manifest: &Manifest,
+    events: &EventBus,
+) -> Result<ScanReport, Box<dyn std::error::Error>> {
+    let total: u64 = manifest.files.iter().map(|f| f.size).sum();
+    let mut tracker = Tracker::new(0, Some(total));
+    let mut report = ScanReport::default();
+
+    let control = download_control::controller();
+    for file in &manifest.files {
+        // 暂停时在文件之间等待，取消时立即停止
+        control.checkpoint().await?;
+        let path = local_path(root, file)?;
+
+        if !path.is_file() {
+            warn!("文件缺失 {}", file.path);
+            report.missing.push(file.clone());
+        } else {
+            let size = path.metadata()?.len();
+            let intact = size == file.size && verify(&path, &file.hash).await?;
+
+            if !intact {
+                warn!("文件已变更 {}", file.path);
+                report.modified.push(file.clone());
+            }
+        }
+
+        if let Some(p) = tracker.advance(file.size) {
+            events.publish(Event::Progress(p));
+        }
+    }
+
+    info!(
+        "校验完成，缺失 {} 个，变更 {} 个",
+        report.missing.len(),
+        report.modified.len()
+    );
+    Ok(report)
+}
+
+/// 只重新下载损坏或缺失的文件
+pub async fn repair(
+    mirrors: &[String],
+    root: &Path,
+    report: &ScanReport,
+    events: &EventBus,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let total: u64 = report.broken().map(|f| f.size).sum();
+    let mut offset: u64 = 0;
+    disk_space::check(root, total)?;
+
+    for file in report.broken() {
+        let path = local_path(root, file)?;
+        let remote_path = format!("client/{}", file.path.replace('\\', "/"));
+        // 损坏的文件不能续传
+        let _ = tokio::fs::remove_file(&path).await;
+        download::fetch(
+            mirrors,
+            &remote_path,
+            &path.to_string_lossy(),
+            offset,
+            total,
+            events,
+        )
+        .await?;
+
+        if !verify(&path, &file.hash).await? {
+            return Err(format!("文件修复后校验失败: {}", file.path).into());
+        }
+
+        offset += file.size;
+    }
+    Ok(())
+}
+
+/// 校验并修复客户端，返回修复的文件数量
+pub async fn handle(
+    auth_token: &AuthToken,
+    events: &EventBus,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let _active = download_control::controller().start();
+    trusted_domain::check(&auth_token.domain)?;
+    let root = helper::game_root();
+    let manifest = fetch_manifest(&auth_token.domain).await?;
+    let report = scan(&root, &manifest, events).await?;
+
+    if let Some(first) = report.broken().next() {
+        let probe = format!("client/{}", first.path.replace('\\', "/"));
+        let mirrors = mirror::select(auth_token, &probe).await;
+        repair(&mirrors, &root, &report, events).await?;
+    }
+
+    events.publish(Event::Finished);
+    Ok(report.broken().count())
+}
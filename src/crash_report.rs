@@ -0,0 +1,150 @@
+use std::backtrace::Backtrace;
+use std::fmt::Write as _;
+use std::fs;
+use std::panic::{self, PanicHookInfo};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use tracing::{error, info};
+
+use crate::{helper, http, redact, VERSION};
+
+/// 未处理的报告扩展名，处理（查看、提交或忽略）后改为 `.txt`
+const PENDING_EXT: &str = "new";
+const HANDLED_EXT: &str = "txt";
+
+/// 提交崩溃报告的地址，相对令牌中的域名
+const SUBMIT_PATH: &str = "api/launcher/crash";
+
+/// 只记录这些不含个人信息的环境变量
+const ENV_VARS: &[&str] = &[
+    "OS",
+    "PROCESSOR_ARCHITECTURE",
+    "PROCESSOR_IDENTIFIER",
+    "NUMBER_OF_PROCESSORS",
+];
+
+/// `business_logic::handle` 当前执行到的阶段
+static STAGE: Mutex<&'static str> = Mutex::new("启动");
+
+pub fn dir() -> PathBuf {
+    helper::data_dir().join("crashes")
+}
+
+pub fn set_stage(stage: &'static str) {
+    if let Ok(mut current) = STAGE.lock() {
+        *current = stage;
+    }
+}
+
+/// 安装 panic 钩子，发布版本 `panic = "abort"` 时也会在退出前写入崩溃报告
+pub fn install() {
+    let default = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let report = build(info);
+        match write(&report) {
+            Ok(path) => error!("启动器崩溃，报告已保存到 {:?}\n{}", path, report),
+            Err(e) => error!("启动器崩溃，保存报告失败 {:?}\n{}", e, report),
+        }
+        default(info);
+    }));
+}
+
+fn build(info: &PanicHookInfo) -> String {
+    let message = info
+        .payload()
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| info.payload().downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "未知错误".to_string());
+    let location = info
+        .location()
+        .map(|l| format!("{}:{}:{}", l.file(), l.line(), l.column()))
+        .unwrap_or_default();
+    let stage = STAGE.lock().map(|s| *s).unwrap_or("未知");
+    let thread = std::thread::current();
+
+    let mut report = String::new();
+    let _ = writeln!(
+        report,
+        "启动器版本: {} ({})",
+        VERSION,
+        env!("CARGO_PKG_VERSION")
+    );
+    let _ = writeln!(report, "时间: {}", helper::timestamp());
+    let _ = writeln!(report, "阶段: {}", stage);
+    let _ = writeln!(report, "线程: {}", thread.name().unwrap_or("未命名"));
+    let _ = writeln!(report, "错误: {}", message);
+    let _ = writeln!(report, "位置: {}", location);
+    let _ = writeln!(report, "\n环境:");
+    let _ = writeln!(
+        report,
+        "  系统: {} {}",
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    );
+    for name in ENV_VARS {
+        if let Ok(value) = std::env::var(name) {
+            let _ = writeln!(report, "  {}: {}", name, value);
+        }
+    }
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let _ = writeln!(report, "  参数: {:?}", args);
+    let _ = writeln!(report, "\n调用栈:\n{}", Backtrace::force_capture());
+
+    // 参数和错误信息中可能带有令牌
+    redact::redact(&report).into_owned()
+}
+
+fn write(report: &str) -> std::io::Result<PathBuf> {
+    let dir = dir();
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("crash-{}.{}", helper::timestamp(), PENDING_EXT));
+    fs::write(&path, report)?;
+    Ok(path)
+}
+
+/// 上次运行留下的、尚未处理的最新崩溃报告
+pub fn pending() -> Option<PathBuf> {
+    fs::read_dir(dir())
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == PENDING_EXT))
+        .max()
+}
+
+/// 把所有未处理的报告标记为已处理，返回最新一份处理后的路径
+pub fn acknowledge() -> Option<PathBuf> {
+    let latest = pending()?;
+    let entries = fs::read_dir(dir()).ok()?;
+    for path in entries.filter_map(|entry| entry.ok().map(|e| e.path())) {
+        if path.extension().is_some_and(|ext| ext == PENDING_EXT) {
+            let _ = fs::rename(&path, path.with_extension(HANDLED_EXT));
+        }
+    }
+    Some(latest.with_extension(HANDLED_EXT))
+}
+
+/// 用默认程序打开最新的崩溃报告
+pub fn open() -> Result<(), Box<dyn std::error::Error>> {
+    let path = acknowledge().ok_or("没有崩溃报告")?;
+    opener::open(&path)?;
+    Ok(())
+}
+
+/// 把最新的崩溃报告提交到 `{domain}/api/launcher/crash`
+pub async fn submit(domain: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let path = pending().ok_or("没有崩溃报告")?;
+    let report = tokio::fs::read_to_string(&path).await?;
+    let url = format!("{}/{}", domain, SUBMIT_PATH);
+    info!("提交崩溃报告 {}", url);
+    http::client()
+        .post(&url)
+        .header(reqwest::header::CONTENT_TYPE, "text/plain; charset=utf-8")
+        .body(report)
+        .send()
+        .await?
+        .error_for_status()?;
+    acknowledge();
+    Ok(())
+}
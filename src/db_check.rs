@@ -1,33 +1,160 @@
-use std::env;
+use std::fmt;
 use std::fs::File;
+use std::io;
 use std::path::Path;
 
 use chksum_md5 as md5;
+use sha2::{Digest, Sha256};
+
+use crate::{hash_cache, helper};
 
 const DB_PATH: &str = "/game/db/compact.sqlite3";
 
-pub fn detect_db(hash: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let root_path = env::current_exe()
-        .expect("获取当前路径失败")
-        .parent()
-        .expect("获取父级目录")
-        .to_str()
-        .expect("转换为字符串失败")
-        .to_string();
-    let exe_path = format!("{}{}", root_path, DB_PATH);
+/// 支持的摘要算法
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    Md5,
+    Sha256,
+    Blake3,
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            HashAlgorithm::Md5 => "md5",
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Blake3 => "blake3",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// 期望的文件摘要，格式为 `算法:十六进制`，不带前缀时视为 MD5 以兼容旧令牌
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpectedDigest {
+    pub algorithm: HashAlgorithm,
+    pub hex: String,
+}
+
+impl HashAlgorithm {
+    /// 十六进制摘要的长度
+    fn hex_len(self) -> usize {
+        match self {
+            HashAlgorithm::Md5 => 32,
+            HashAlgorithm::Sha256 | HashAlgorithm::Blake3 => 64,
+        }
+    }
+}
+
+impl ExpectedDigest {
+    pub fn parse(value: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let value = value.trim();
+        let (algorithm, hex) = match value.split_once(':') {
+            None => (HashAlgorithm::Md5, value),
+            Some((prefix, hex)) => match prefix.trim().to_lowercase().as_str() {
+                "md5" => (HashAlgorithm::Md5, hex),
+                "sha256" => (HashAlgorithm::Sha256, hex),
+                "blake3" => (HashAlgorithm::Blake3, hex),
+                other => return Err(format!("不支持的摘要算法: {}", other).into()),
+            },
+        };
+        let hex = hex.trim().to_lowercase();
+        if hex.len() != algorithm.hex_len() || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(format!("无效的 {} 摘要: {}", algorithm, hex).into());
+        }
+        Ok(ExpectedDigest { algorithm, hex })
+    }
+}
+
+impl fmt::Display for ExpectedDigest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.algorithm, self.hex)
+    }
+}
+
+/// 本地 DB 文件路径
+pub fn db_path() -> String {
+    format!("{}{}", helper::game_root().display(), DB_PATH)
+}
+
+/// 校验 DB 文件；`force` 为 true 时跳过摘要缓存
+pub fn detect_db(hash: &str, force: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let exe_path = db_path();
 
     if !Path::exists(exe_path.as_ref()) {
         return Err("文件不存在".into());
     }
 
-    let file = File::open(&exe_path)?;
-    let digest = md5::chksum(file)?;
-
-    if digest.to_hex_lowercase() != hash {
+    if !matches(exe_path.as_ref(), &ExpectedDigest::parse(hash)?, force)? {
         return Err("文件已变更".into());
     }
 
     Ok(())
 }
 
+/// 文件摘要是否与期望值一致
+pub fn matches(
+    path: &Path,
+    expected: &ExpectedDigest,
+    force: bool,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    Ok(hash_cache::hash(path, expected.algorithm, force)? == expected.hex)
+}
+
+/// 计算文件摘要（小写十六进制）
+pub fn hash_file(
+    path: &Path,
+    algorithm: HashAlgorithm,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let mut file = File::open(path)?;
+    let hex = match algorithm {
+        HashAlgorithm::Md5 => md5::chksum(file)?.to_hex_lowercase(),
+        HashAlgorithm::Sha256 => {
+            let mut hasher = Sha256::new();
+            io::copy(&mut file, &mut hasher)?;
+            hex::encode(hasher.finalize())
+        }
+        HashAlgorithm::Blake3 => {
+            let mut hasher = blake3::Hasher::new();
+            io::copy(&mut file, &mut hasher)?;
+            hasher.finalize().to_hex().to_string()
+        }
+    };
+    Ok(hex)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
+    const MD5: &str = "d41d8cd98f00b204e9800998ecf8427e";
+    const SHA256: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+    #[test]
+    fn parse_without_prefix() {
+        let digest = ExpectedDigest::parse(&format!("  {}\n", MD5.to_uppercase())).unwrap();
+        assert_eq!(digest.algorithm, HashAlgorithm::Md5);
+        assert_eq!(digest.hex, MD5);
+    }
+
+    #[test]
+    fn parse_with_prefix() {
+        let digest = ExpectedDigest::parse(&format!(" SHA256:{} ", SHA256)).unwrap();
+        assert_eq!(digest.algorithm, HashAlgorithm::Sha256);
+        assert_eq!(digest.hex, SHA256);
+        let digest = ExpectedDigest::parse(&format!("Blake3:{}", SHA256)).unwrap();
+        assert_eq!(digest.algorithm, HashAlgorithm::Blake3);
+        assert_eq!(digest.to_string(), format!("blake3:{}", SHA256));
+    }
+
+    #[test]
+    fn reject_invalid_digest() {
+        // 长度与算法不符
+        assert!(ExpectedDigest::parse(&format!("md5:{}", SHA256)).is_err());
+        assert!(ExpectedDigest::parse(&format!("sha256:{}", MD5)).is_err());
+        assert!(ExpectedDigest::parse("").is_err());
+        // 非十六进制字符
+        assert!(ExpectedDigest::parse(&MD5.replace('d', "g")).is_err());
+        assert!(ExpectedDigest::parse(&format!("crc32:{}", MD5)).is_err());
+    }
+}
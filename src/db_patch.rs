@@ -0,0 +1,316 @@
+//! DB 差分补丁
+//!
+//! SQLite 按页修改数据，补丁以固定大小的块为单位：新文件中与旧文件某个块相同的块记录为复制，
+//! 其余块直接保存数据，整个补丁再用 zstd 压缩。
+//!
+//! 格式（压缩前）：
+//! `PLAADB1\0` | 块大小 u32 | 新文件长度 u64 | 操作...
+//! - `1` 复制：旧文件块序号 u64，块数量 u32
+//! - `2` 数据：长度 u32，数据
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 8] = b"PLAADB1\0";
+const OP_COPY: u8 = 1;
+const OP_DATA: u8 = 2;
+
+/// SQLite 默认页大小
+#[cfg_attr(not(test), allow(dead_code))]
+pub const BLOCK_SIZE: u32 = 4096;
+
+#[cfg_attr(not(test), allow(dead_code))]
+fn block_hash(block: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    block.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+/// 根据旧文件和新文件生成补丁，返回补丁中直接保存的数据字节数。
+/// 只有 `db_patch` 工具使用，启动器本身只应用补丁
+#[cfg_attr(not(test), allow(dead_code))]
+pub fn diff(old: &Path, new: &Path, patch: &Path) -> io::Result<u64> {
+    let old_data = std::fs::read(old)?;
+    let new_data = std::fs::read(new)?;
+    let block_size = BLOCK_SIZE as usize;
+
+    // 旧文件每个块的哈希，哈希相同时再比较内容
+    let mut index: HashMap<u64, Vec<u64>> = HashMap::new();
+    for (i, block) in old_data.chunks(block_size).enumerate() {
+        index.entry(block_hash(block)).or_default().push(i as u64);
+    }
+    let find = |block: &[u8]| -> Option<u64> {
+        index.get(&block_hash(block))?.iter().copied().find(|&i| {
+            let start = i as usize * block_size;
+            let end = (start + block_size).min(old_data.len());
+            &old_data[start..end] == block
+        })
+    };
+
+    let mut writer = zstd::Encoder::new(BufWriter::new(File::create(patch)?), 19)?;
+    writer.write_all(MAGIC)?;
+    writer.write_all(&BLOCK_SIZE.to_le_bytes())?;
+    writer.write_all(&(new_data.len() as u64).to_le_bytes())?;
+
+    let mut literal_bytes = 0u64;
+    let mut copy: Option<(u64, u32)> = None;
+    let mut literal: Vec<u8> = Vec::new();
+
+    let flush_copy = |writer: &mut dyn Write, copy: &mut Option<(u64, u32)>| -> io::Result<()> {
+        if let Some((start, count)) = copy.take() {
+            writer.write_all(&[OP_COPY])?;
+            writer.write_all(&start.to_le_bytes())?;
+            writer.write_all(&count.to_le_bytes())?;
+        }
+        Ok(())
+    };
+    let flush_literal = |writer: &mut dyn Write, literal: &mut Vec<u8>| -> io::Result<()> {
+        if !literal.is_empty() {
+            writer.write_all(&[OP_DATA])?;
+            writer.write_all(&(literal.len() as u32).to_le_bytes())?;
+            writer.write_all(literal)?;
+            literal.clear();
+        }
+        Ok(())
+    };
+
+    for block in new_data.chunks(block_size) {
+        match find(block) {
+            Some(i) => {
+                flush_literal(&mut writer, &mut literal)?;
+                match copy.as_mut() {
+                    // 连续的块合并为一次复制
+                    Some((start, count)) if *start + *count as u64 == i => *count += 1,
+                    _ => {
+                        flush_copy(&mut writer, &mut copy)?;
+                        copy = Some((i, 1));
+                    }
+                }
+            }
+            None => {
+                flush_copy(&mut writer, &mut copy)?;
+                literal.extend_from_slice(block);
+                literal_bytes += block.len() as u64;
+                if literal.len() >= 1024 * 1024 {
+                    flush_literal(&mut writer, &mut literal)?;
+                }
+            }
+        }
+    }
+    flush_copy(&mut writer, &mut copy)?;
+    flush_literal(&mut writer, &mut literal)?;
+
+    writer.finish()?.flush()?;
+    Ok(literal_bytes)
+}
+
+/// 把补丁应用到旧文件，生成新文件。补丁来自网络，所有位置和长度都要检查
+pub fn apply(old: &Path, patch: &Path, new: &Path) -> io::Result<()> {
+    let mut old_file = File::open(old)?;
+    let old_len = old_file.metadata()?.len();
+    let mut reader = zstd::Decoder::new(BufReader::new(File::open(patch)?))?;
+    let mut writer = BufWriter::new(File::create(new)?);
+
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid("补丁格式错误"));
+    }
+    let block_size = read_u32(&mut reader)? as u64;
+    let new_len = read_u64(&mut reader)?;
+    if block_size == 0 {
+        return Err(invalid("补丁块大小无效"));
+    }
+
+    let mut written = 0u64;
+    let mut op = [0u8; 1];
+    loop {
+        match reader.read(&mut op)? {
+            0 => break,
+            _ => match op[0] {
+                OP_COPY => {
+                    let start = read_u64(&mut reader)?
+                        .checked_mul(block_size)
+                        .ok_or_else(|| invalid("补丁复制位置无效"))?;
+                    // u32 * u32 不会溢出 u64
+                    let len = read_u32(&mut reader)? as u64 * block_size;
+                    let len = len.min(old_len.saturating_sub(start));
+                    if len > new_len - written {
+                        return Err(invalid("补丁超出新文件长度"));
+                    }
+                    old_file.seek(SeekFrom::Start(start))?;
+                    written += io::copy(&mut (&mut old_file).take(len), &mut writer)?;
+                }
+                OP_DATA => {
+                    let len = read_u32(&mut reader)? as u64;
+                    if len > new_len - written {
+                        return Err(invalid("补丁超出新文件长度"));
+                    }
+                    written += io::copy(&mut (&mut reader).take(len), &mut writer)?;
+                }
+                _ => return Err(invalid("补丁操作无效")),
+            },
+        }
+    }
+
+    if written != new_len {
+        return Err(invalid("补丁应用后长度不一致"));
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+    use std::fs;
+
+    /// 由种子生成内容不同的整块数据
+    fn blocks(seeds: &[u8]) -> Vec<u8> {
+        seeds
+            .iter()
+            .flat_map(|&seed| {
+                (0..BLOCK_SIZE).map(move |i| (i as u8).wrapping_mul(seed).wrapping_add(seed))
+            })
+            .collect()
+    }
+
+    /// 生成并应用补丁，确认结果与新文件一致，返回补丁中的数据字节数
+    fn round_trip(name: &str, old: &[u8], new: &[u8]) -> u64 {
+        let dir = temp_dir(&format!("db-patch-{}", name));
+        let (old_path, new_path) = (dir.join("old"), dir.join("new"));
+        let (patch_path, out_path) = (dir.join("patch"), dir.join("out"));
+        fs::write(&old_path, old).unwrap();
+        fs::write(&new_path, new).unwrap();
+
+        let literal = diff(&old_path, &new_path, &patch_path).unwrap();
+        apply(&old_path, &patch_path, &out_path).unwrap();
+        assert_eq!(fs::read(&out_path).unwrap(), new);
+
+        let _ = fs::remove_dir_all(&dir);
+        literal
+    }
+
+    /// 手工构造补丁内容，返回应用结果
+    fn apply_raw(name: &str, old: &[u8], body: &[u8]) -> io::Result<()> {
+        let dir = temp_dir(&format!("db-patch-{}", name));
+        let (old_path, patch_path) = (dir.join("old"), dir.join("patch"));
+        fs::write(&old_path, old).unwrap();
+        fs::write(&patch_path, zstd::encode_all(body, 0).unwrap()).unwrap();
+        let res = apply(&old_path, &patch_path, &dir.join("out"));
+        let _ = fs::remove_dir_all(&dir);
+        res
+    }
+
+    fn header(block_size: u32, new_len: u64) -> Vec<u8> {
+        let mut body = MAGIC.to_vec();
+        body.extend_from_slice(&block_size.to_le_bytes());
+        body.extend_from_slice(&new_len.to_le_bytes());
+        body
+    }
+
+    #[test]
+    fn identical_files() {
+        let data = blocks(&[1, 2, 3]);
+        assert_eq!(round_trip("identical", &data, &data), 0);
+    }
+
+    #[test]
+    fn appended_blocks() {
+        let literal = round_trip("appended", &blocks(&[1, 2, 3]), &blocks(&[1, 2, 3, 4]));
+        assert_eq!(literal, BLOCK_SIZE as u64);
+    }
+
+    #[test]
+    fn truncated_blocks() {
+        assert_eq!(
+            round_trip("truncated", &blocks(&[1, 2, 3]), &blocks(&[1, 2])),
+            0
+        );
+    }
+
+    #[test]
+    fn shuffled_blocks() {
+        let literal = round_trip("shuffled", &blocks(&[1, 2, 3, 4]), &blocks(&[3, 1, 4, 2]));
+        assert_eq!(literal, 0);
+    }
+
+    #[test]
+    fn size_not_multiple_of_block() {
+        let mut old = blocks(&[1, 2]);
+        old.extend_from_slice(&[9; 100]);
+        let mut new = blocks(&[2, 1, 5]);
+        new.extend_from_slice(&[7; 1234]);
+        round_trip("unaligned", &old, &new);
+        // 旧文件末尾不足一块的部分也能复制
+        round_trip("unaligned-tail", &new, &old);
+    }
+
+    #[test]
+    fn corrupt_patch() {
+        let old = blocks(&[1, 2]);
+        let dir = temp_dir("db-patch-corrupt");
+        fs::write(dir.join("old"), &old).unwrap();
+        fs::write(dir.join("patch"), b"not a patch").unwrap();
+        assert!(apply(&dir.join("old"), &dir.join("patch"), &dir.join("out")).is_err());
+        let _ = fs::remove_dir_all(&dir);
+
+        // 格式标记错误
+        let mut body = header(BLOCK_SIZE, 0);
+        body[0] = b'X';
+        assert!(apply_raw("magic", &old, &body).is_err());
+
+        // 未知操作
+        let mut body = header(BLOCK_SIZE, 0);
+        body.push(9);
+        assert!(apply_raw("op", &old, &body).is_err());
+
+        // 复制位置溢出
+        let mut body = header(BLOCK_SIZE, BLOCK_SIZE as u64);
+        body.push(OP_COPY);
+        body.extend_from_slice(&u64::MAX.to_le_bytes());
+        body.extend_from_slice(&1u32.to_le_bytes());
+        assert!(apply_raw("overflow", &old, &body).is_err());
+
+        // 数据超出声明的新文件长度
+        let mut body = header(BLOCK_SIZE, 4);
+        body.push(OP_DATA);
+        body.extend_from_slice(&8u32.to_le_bytes());
+        body.extend_from_slice(&[0; 8]);
+        assert!(apply_raw("too-long", &old, &body).is_err());
+
+        // 复制超出声明的新文件长度
+        let mut body = header(BLOCK_SIZE, 4);
+        body.push(OP_COPY);
+        body.extend_from_slice(&0u64.to_le_bytes());
+        body.extend_from_slice(&2u32.to_le_bytes());
+        assert!(apply_raw("copy-too-long", &old, &body).is_err());
+
+        // 数据被截断
+        let mut body = header(BLOCK_SIZE, 8);
+        body.push(OP_DATA);
+        body.extend_from_slice(&8u32.to_le_bytes());
+        body.extend_from_slice(&[0; 4]);
+        assert!(apply_raw("short", &old, &body).is_err());
+    }
+}
@@ -0,0 +1,159 @@
+use std::cmp::Reverse;
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use tracing::{info, warn};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::db_check::{ExpectedDigest, HashAlgorithm};
+use crate::{
+    crash_report, db_check, game_check, hash_cache, helper, launcher_config, logging, redact,
+    regedit, system_config, VERSION,
+};
+
+/// 每类只附带最近的几个文件
+const RECENT_FILES: usize = 3;
+
+/// 默认保存位置：数据目录下的 `diagnostics-{时间}.zip`
+pub fn default_path() -> PathBuf {
+    helper::data_dir().join(format!("diagnostics-{}.zip", helper::timestamp()))
+}
+
+/// 本地 DB 的摘要，算法与期望摘要一致，没有期望摘要时用 MD5
+fn db_digest(expected: Option<&ExpectedDigest>) -> String {
+    let algorithm = expected.map_or(HashAlgorithm::Md5, |e| e.algorithm);
+    let path = PathBuf::from(db_check::db_path());
+    if !path.is_file() {
+        return "文件不存在".to_string();
+    }
+    match hash_cache::hash(&path, algorithm, false) {
+        Ok(hex) => format!("{}:{}", algorithm, hex),
+        Err(e) => format!("计算失败 {}", e),
+    }
+}
+
+/// 版本、目录、DB、协议注册等基本信息，`expected_hash` 为令牌中的 DB 摘要。
+/// 需要计算 DB 摘要，应在后台线程中调用。
+pub fn summary(expected_hash: Option<&str>) -> String {
+    let expected = expected_hash
+        .filter(|h| !h.is_empty())
+        .and_then(|h| ExpectedDigest::parse(h).ok());
+    let actual = db_digest(expected.as_ref());
+
+    let mut text = String::new();
+    let _ = writeln!(
+        text,
+        "启动器版本: {} ({})",
+        VERSION,
+        env!("CARGO_PKG_VERSION")
+    );
+    let _ = writeln!(text, "生成时间: {}", helper::timestamp());
+    let _ = writeln!(
+        text,
+        "系统: {} {}",
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    );
+    let _ = writeln!(
+        text,
+        "协议注册: {}",
+        if regedit::detecting() {
+            "正常"
+        } else {
+            "未注册或路径不匹配"
+        }
+    );
+    let _ = writeln!(text, "启动器目录: {}", helper::exe_dir().display());
+    let _ = writeln!(text, "{}", game_check::validate(&helper::game_root()));
+    let _ = writeln!(text, "DB 文件: {}", db_check::db_path());
+    let _ = writeln!(text, "DB 摘要: {}", actual);
+    match &expected {
+        Some(expected) => {
+            let _ = writeln!(text, "期望摘要: {}", expected);
+            let same = actual == expected.to_string();
+            let _ = writeln!(text, "DB 一致: {}", if same { "是" } else { "否" });
+        }
+        None => {
+            let _ = writeln!(text, "期望摘要: 未知（未通过官网启动或令牌未提供）");
+        }
+    }
+    redact::redact(&text).into_owned()
+}
+
+/// 配置文件中代理地址可能带有账号密码
+fn config_json() -> String {
+    let mut config = launcher_config::load();
+    if config.proxy.is_some() {
+        config.proxy = Some("******".to_string());
+    }
+    serde_json::to_string_pretty(&config).unwrap_or_default()
+}
+
+/// 目录中最近修改的几个文件
+fn recent_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<(SystemTime, PathBuf)> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .filter_map(|entry| {
+                    let modified = entry.metadata().ok()?.modified().ok()?;
+                    entry.path().is_file().then(|| (modified, entry.path()))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    files.sort_by_key(|(modified, _)| Reverse(*modified));
+    files
+        .into_iter()
+        .take(RECENT_FILES)
+        .map(|(_, path)| path)
+        .collect()
+}
+
+/// 生成诊断压缩包，所有文本都经过脱敏。需要计算 DB 摘要，应在后台线程中调用。
+pub fn create(path: &Path, expected_hash: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    info!("生成诊断报告 {:?}", path);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut entries: Vec<(String, String)> = vec![
+        ("summary.txt".to_string(), summary(expected_hash)),
+        ("launcher.json".to_string(), config_json()),
+    ];
+    match fs::read(system_config::file_path()) {
+        Ok(data) => entries.push((
+            "system.cfg".to_string(),
+            String::from_utf8_lossy(&data).into_owned(),
+        )),
+        Err(e) => warn!("读取 system.cfg 失败 {:?}", e),
+    }
+    for (folder, dir) in [("logs", logging::dir()), ("crashes", crash_report::dir())] {
+        for file in recent_files(&dir) {
+            let Some(name) = file.file_name() else {
+                continue;
+            };
+            match fs::read(&file) {
+                Ok(data) => entries.push((
+                    format!("{}/{}", folder, name.to_string_lossy()),
+                    String::from_utf8_lossy(&data).into_owned(),
+                )),
+                Err(e) => warn!("读取文件失败 {:?} {:?}", file, e),
+            }
+        }
+    }
+
+    let mut zip = ZipWriter::new(File::create(path)?);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (name, content) in entries {
+        zip.start_file(name, options)?;
+        zip.write_all(redact::redact(&content).as_bytes())?;
+    }
+    zip.finish()?;
+    info!("诊断报告已保存 {:?}", path);
+    Ok(())
+}
@@ -0,0 +1,67 @@
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+
+use tracing::info;
+use windows::core::HSTRING;
+use windows::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+use crate::progress::format_bytes;
+
+/// 临时文件、备份文件等额外预留的空间
+const HEADROOM: u64 = 64 * 1024 * 1024;
+
+/// 磁盘空间不足
+#[derive(Debug)]
+pub struct InsufficientSpace {
+    pub required: u64,
+    pub available: u64,
+}
+
+impl fmt::Display for InsufficientSpace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "磁盘空间不足：需要 {}，可用 {}",
+            format_bytes(self.required as f64),
+            format_bytes(self.available as f64)
+        )
+    }
+}
+
+impl Error for InsufficientSpace {}
+
+/// 路径所在磁盘的可用空间，路径不存在时使用最近的已存在的上级目录
+pub fn available(path: &Path) -> Result<u64, Box<dyn Error>> {
+    let dir = path
+        .ancestors()
+        .find(|p| p.is_dir())
+        .ok_or("找不到可用的目录")?;
+
+    let mut free: u64 = 0;
+    unsafe {
+        GetDiskFreeSpaceExW(
+            &HSTRING::from(dir.as_os_str()),
+            Some(&mut free as *mut u64),
+            None,
+            None,
+        )?;
+    }
+    Ok(free)
+}
+
+/// 检查写入 `size` 字节前磁盘空间是否足够，包含额外预留空间
+pub fn check(path: &Path, size: u64) -> Result<(), Box<dyn Error>> {
+    let required = size + size / 20 + HEADROOM;
+    let available = available(path)?;
+    info!("磁盘空间检查 需要 {} 可用 {}", required, available);
+
+    if available < required {
+        return Err(InsufficientSpace {
+            required,
+            available,
+        }
+        .into());
+    }
+    Ok(())
+}
@@ -1,77 +1,569 @@
-use crate::Task::Progress;
-use crate::{download, protocol, PAUSE_UPGRADE};
-use futures::StreamExt;
-use reqwest::Client;
-use std::env;
-use std::path::Path;
-use std::time::Duration;
+use crate::db_check::ExpectedDigest;
+use crate::download_control::Cancelled;
+use crate::events::{Event, EventBus};
+use crate::progress::Tracker;
+use crate::{
+    db_check, db_patch, disk_space, download, download_control, download_meta, hash_cache, http,
+    lan_peer, launcher_config, local_source, manifest, mirror, protocol, rate_limit, segmented,
+    trusted_domain,
+};
+use async_compression::tokio::write::{GzipDecoder, ZstdDecoder};
+use futures::{Stream, StreamExt};
+use reqwest::header::{HeaderMap, CONTENT_ENCODING, IF_RANGE, RANGE};
+use reqwest::StatusCode;
+use std::path::{Path, PathBuf};
 use tokio::fs;
-use tokio::fs::File;
-use tokio::io::AsyncWriteExt;
-use tracing::{debug, info};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tracing::{debug, info, warn};
 
-pub async fn download(url: &str, filename: &str) -> Result<(), Box<dyn std::error::Error>> {
+/// 签名的更新清单，列出 DB 等更新文件的摘要和大小
+const UPDATE_MANIFEST: &str = "update_manifest.json";
+
+/// 压缩格式，根据文件扩展名或 `Content-Encoding` 判断
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    pub(crate) fn detect(url: &str, headers: &HeaderMap) -> Self {
+        let encoding = headers
+            .get(CONTENT_ENCODING)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let path = url
+            .split(['?', '#'])
+            .next()
+            .unwrap_or(url)
+            .to_ascii_lowercase();
+
+        if encoding == "zstd" || path.ends_with(".zst") {
+            Compression::Zstd
+        } else if encoding == "gzip" || path.ends_with(".gz") {
+            Compression::Gzip
+        } else {
+            Compression::None
+        }
+    }
+}
+
+/// 下载文件到临时文件，解压并校验通过后替换目标文件，最后发送完成通知。
+/// `size` 为清单中解压后的文件大小，已知时下载前按它检查磁盘空间
+pub async fn download(
+    mirrors: &[String],
+    remote_path: &str,
+    filename: &str,
+    expected: Option<ExpectedDigest>,
+    size: Option<u64>,
+    events: &EventBus,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let record = match download_meta::check(mirrors, remote_path, filename).await {
+        download_meta::Check::NotModified if !local_matches(filename, expected.as_ref()).await => {
+            warn!("服务器文件未变化，但本地文件与期望摘要不一致，重新下载");
+            None
+        }
+        download_meta::Check::NotModified => {
+            events.publish(Event::Finished);
+            info!("文件未变化，跳过下载");
+            return Ok(());
+        }
+        download_meta::Check::Modified(record) => record,
+    };
+
+    let temp = format!("{}.download", filename);
+    if let Some(size) = size {
+        disk_space::check(Path::new(&temp), size)?;
+    }
+    fetch(mirrors, remote_path, &temp, 0, 0, events).await?;
+
+    if let Some(expected) = expected.clone() {
+        let temp_path = PathBuf::from(&temp);
+        let ok = tokio::task::spawn_blocking(move || {
+            db_check::matches(&temp_path, &expected, true).map_err(|e| e.to_string())
+        })
+        .await??;
+        if !ok {
+            let _ = fs::remove_file(&temp).await;
+            hash_cache::forget(Path::new(&temp));
+            return Err("下载的文件校验失败".into());
+        }
+    }
+    fs::rename(&temp, filename).await?;
+    hash_cache::rename(Path::new(&temp), Path::new(filename));
+    if let Some(expected) = &expected {
+        lan_peer::share(remote_path, Path::new(filename), expected);
+    }
+    if let Some(record) = record {
+        download_meta::store(filename, record);
+    }
+
+    events.publish(Event::Finished);
+    info!("下载完成");
+    Ok(())
+}
+
+/// 本地文件与期望摘要一致，没有期望摘要时认为一致
+async fn local_matches(filename: &str, expected: Option<&ExpectedDigest>) -> bool {
+    let Some(expected) = expected.cloned() else {
+        return true;
+    };
+    let path = PathBuf::from(filename);
+    tokio::task::spawn_blocking(move || db_check::matches(&path, &expected, false).unwrap_or(false))
+        .await
+        .unwrap_or(false)
+}
+
+/// 下载单个文件，不发送完成通知。
+/// 进度按 `(offset + 已下载) / total` 计算，`total` 为 0 时使用响应长度，便于多个文件共用一个进度条；
+/// 两者都未知时界面显示不确定进度。
+/// 压缩的数据边下载边解压写入磁盘，进度按压缩后的字节计算。
+/// 目标文件已存在且未压缩时尝试断点续传，不需要续传的调用方应先删除旧文件。
+/// 按顺序尝试每个镜像的 `{镜像}/{remote_path}`，某个镜像中途失败时换下一个镜像从同一位置继续；
+/// 所有镜像都出现网络临时错误时按指数退避重试。
+/// 镜像也可以是 `file://` 地址或本地、共享目录，此时直接复制文件。
+pub async fn fetch(
+    mirrors: &[String],
+    remote_path: &str,
+    filename: &str,
+    offset: u64,
+    total: u64,
+    events: &EventBus,
+) -> Result<u64, Box<dyn std::error::Error>> {
+    http::retry(|| async {
+        let mut last_error: Option<Box<dyn std::error::Error>> = None;
+        for mirror in mirrors {
+            let url = format!("{}/{}", mirror, remote_path);
+            match fetch_file(&url, filename, offset, total, events).await {
+                Ok(size) => return Ok(size),
+                Err(e) if download_control::is_cancelled(e.as_ref()) => return Err(e),
+                Err(e) => {
+                    warn!("镜像下载失败 {} {}", mirror, e);
+                    last_error = Some(e);
+                }
+            }
+        }
+        Err(last_error.unwrap_or_else(|| "没有可用的下载地址".into()))
+    })
+    .await
+}
+
+/// 服务器支持时分段并发下载，否则或分段失败时使用单个连接
+async fn fetch_file(
+    url: &str,
+    filename: &str,
+    offset: u64,
+    total: u64,
+    events: &EventBus,
+) -> Result<u64, Box<dyn std::error::Error>> {
+    let connections = launcher_config::load().connections;
+    let existing = Path::new(filename).exists();
+
+    if connections > 1 && !existing && !local_source::is_local(url) {
+        if let Some(remote) = segmented::probe(url).await {
+            if segmented::segment_count(remote.length, connections) > 1 {
+                let res =
+                    segmented::fetch(url, filename, offset, total, &remote, connections, events)
+                        .await;
+                match res {
+                    Ok(size) => return Ok(size),
+                    Err(e) if download_control::is_cancelled(e.as_ref()) => return Err(e),
+                    Err(e) => warn!("分段下载失败，改为单线程下载 {}", e),
+                }
+            }
+        }
+    }
+
+    fetch_once(url, filename, offset, total, events).await
+}
+
+async fn fetch_once(
+    url: &str,
+    filename: &str,
+    offset: u64,
+    total: u64,
+    events: &EventBus,
+) -> Result<u64, Box<dyn std::error::Error>> {
     debug!("开始下载文件 {} ====> {}", url, filename);
 
     let path = Path::new(&filename);
-    let client = Client::new();
-    let response = client.get(url).send().await?;
-    let total_size = response.content_length().unwrap_or(0);
+    let control = download_control::controller();
+    control.checkpoint().await?;
 
-    info!("文件大小 {} bytes", total_size);
+    let existing = fs::metadata(path).await.map(|m| m.len()).unwrap_or(0);
 
-    let mut downloaded: u64 = 0;
+    if let Some(source) = local_source::path(url) {
+        let compression = Compression::detect(url, &HeaderMap::new());
+        let length = fs::metadata(&source).await?.len();
+        // 与 HTTP 一致：压缩数据或已有文件比源文件大时重新复制
+        let resumed = if compression == Compression::None && existing <= length {
+            existing
+        } else {
+            0
+        };
+        if resumed > 0 {
+            info!("尝试断点续传，已有 {} bytes", resumed);
+        }
+        let stream = local_source::open(&source, resumed).await?;
+        return receive(
+            path,
+            Box::pin(stream),
+            compression,
+            resumed,
+            length - resumed,
+            tracker(offset, total, resumed, length - resumed),
+            events,
+        )
+        .await;
+    }
+
+    let client = http::client();
+    let mut response = if existing > 0 {
+        info!("尝试断点续传，已有 {} bytes", existing);
+        let mut request = client
+            .get(url)
+            .header(RANGE, format!("bytes={}-", existing));
+        // 服务器上的文件变化时返回完整文件，从头下载
+        if let Some(validator) = download_meta::if_range(filename, url) {
+            request = request.header(IF_RANGE, validator);
+        }
+        request.send().await?
+    } else {
+        client.get(url).send().await?
+    };
+    let mut compression = Compression::detect(url, response.headers());
+    if response.status() == StatusCode::RANGE_NOT_SATISFIABLE
+        || (response.status() == StatusCode::PARTIAL_CONTENT && compression != Compression::None)
+    {
+        // 无法续传，重新下载完整文件
+        response = client.get(url).send().await?;
+        compression = Compression::detect(url, response.headers());
+    }
+    let response = response.error_for_status()?;
+    let resumed = if response.status() == StatusCode::PARTIAL_CONTENT {
+        existing
+    } else {
+        0
+    };
+    let content_length = response.content_length().unwrap_or(0);
+    if resumed == 0 {
+        download_meta::store_partial(filename, url, download_meta::validator(response.headers()));
+    }
+
+    let size = receive(
+        path,
+        Box::pin(response.bytes_stream()),
+        compression,
+        resumed,
+        content_length,
+        tracker(offset, total, resumed, content_length),
+        events,
+    )
+    .await?;
+    download_meta::forget(filename);
+    Ok(size)
+}
+
+/// 进度按 `(offset + 已下载) / total` 计算，`total` 为 0 时使用本次续传和响应的长度
+fn tracker(offset: u64, total: u64, resumed: u64, content_length: u64) -> Tracker {
+    let total = if total > 0 {
+        total
+    } else {
+        resumed + content_length
+    };
+    Tracker::new(offset + resumed, Some(total))
+}
+
+/// 把数据流写入文件，HTTP 和本地更新源共用，处理进度、限速、暂停和取消。
+/// 返回文件大小，包括续传之前已有的部分
+async fn receive<S, B, E>(
+    path: &Path,
+    mut stream: S,
+    compression: Compression,
+    resumed: u64,
+    content_length: u64,
+    mut tracker: Tracker,
+    events: &EventBus,
+) -> Result<u64, Box<dyn std::error::Error>>
+where
+    S: Stream<Item = Result<B, E>> + Unpin,
+    B: AsRef<[u8]>,
+    E: Into<Box<dyn std::error::Error>>,
+{
+    let control = download_control::controller();
+    // 前面文件的进度
+    let offset = tracker.done() - resumed;
+
+    info!(
+        "文件大小 {} bytes 续传 {} bytes 压缩格式 {:?}",
+        content_length, resumed, compression
+    );
+
+    // 压缩数据解压后的大小未知，至少需要压缩数据的大小
+    disk_space::check(path, content_length)?;
 
     // 获取文件目录
-    if let Some(parent) = Path::new(path).parent() {
+    if let Some(parent) = path.parent() {
         // 创建目录（如果不存在）
         info!("目录不存在，自动创建! {:?}", parent);
         fs::create_dir_all(parent).await?;
     }
 
-    let mut file = File::create(path).await?;
+    let file = if resumed > 0 {
+        OpenOptions::new().append(true).open(path).await?
+    } else {
+        File::create(path).await?
+    };
+    let mut writer: Box<dyn AsyncWrite + Unpin + Send> = match compression {
+        Compression::None => Box::new(file),
+        Compression::Gzip => Box::new(GzipDecoder::new(file)),
+        Compression::Zstd => Box::new(ZstdDecoder::new(file)),
+    };
 
-    // 异步处理数据流
-    let mut stream = response.bytes_stream();
-    let s3 = super::SENDER.get().unwrap();
+    // 读取流并计算进度，取消时立即停止等待
+    let result: Result<(), Box<dyn std::error::Error>> = async {
+        while let Some(chunk) = tokio::select! {
+            chunk = stream.next() => chunk,
+            _ = control.wait_cancel() => return Err(Cancelled.into()),
+        } {
+            let chunk = chunk.map_err(Into::into)?;
+            let chunk = chunk.as_ref();
+            control.checkpoint().await?;
+            tokio::select! {
+                _ = rate_limit::limiter().acquire(chunk.len() as u64) => {}
+                _ = control.wait_cancel() => return Err(Cancelled.into()),
+            }
+            writer.write_all(chunk).await?;
 
-    // 读取流并计算进度
-    while let Some(chunk) = stream.next().await {
-        if let Some(pause) = PAUSE_UPGRADE.get() {
-            while pause.load(std::sync::atomic::Ordering::Relaxed) {
-                debug!("下载任务已暂停");
-                tokio::time::sleep(Duration::from_millis(500)).await;
+            if let Some(p) = tracker.advance(chunk.len() as u64) {
+                debug!("已下载: {}", p);
+                events.publish(Event::Progress(p));
             }
         }
+        Ok(())
+    }
+    .await;
 
-        let chunk = chunk?;
-        file.write_all(&chunk).await?;
-        downloaded += chunk.len() as u64;
-
-        if total_size > 0 {
-            let percentage = downloaded as f64 / total_size as f64 * 100.0;
-            info!("已下载: {:.2}% {} {}", percentage, downloaded, total_size);
-            let res = s3.lock().await.send(Progress(percentage)).await;
-            debug!("进度通知结果 {:?}", res);
+    if let Err(e) = result {
+        let _ = writer.shutdown().await;
+        drop(writer);
+        // 压缩数据无法续传；取消时按需保留已下载的部分，网络错误时保留以便重试续传
+        let resumable = if download_control::is_cancelled(e.as_ref()) {
+            control.cancelled() == Some(false)
         } else {
-            info!("已下载: {} bytes", downloaded);
+            http::is_transient(e.as_ref())
+        };
+        let keep = resumable && compression == Compression::None;
+        if keep {
+            info!("保留已下载的部分 {:?}", path);
+        } else {
+            let _ = fs::remove_file(path).await;
         }
+        return Err(e);
     }
-    let _res = s3.lock().await.send(Progress(100f64)).await;
-    info!("下载完成");
-    Ok(())
+
+    // 结束解压并刷新到磁盘
+    writer.shutdown().await?;
+    info!("已下载: {} bytes", tracker.done() - offset);
+    Ok(tracker.done() - offset)
+}
+
+/// 本地 DB 与补丁源版本一致时下载差分补丁并应用，成功返回 true
+async fn patch_db(
+    mirrors: &[String],
+    db_path: &str,
+    expected: &ExpectedDigest,
+    size: u64,
+    events: &EventBus,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    if !Path::new(db_path).is_file() {
+        return Ok(false);
+    }
+
+    let local_path = PathBuf::from(db_path);
+    let algorithm = expected.algorithm;
+    let local = tokio::task::spawn_blocking(move || {
+        hash_cache::hash(&local_path, algorithm, false).map_err(|e| e.to_string())
+    })
+    .await??;
+
+    let remote_path = format!("patches/{}_{}.patch", local, expected.hex);
+    let patch = format!("{}.patch", db_path);
+    let temp = format!("{}.download", db_path);
+    info!("尝试差分更新 {}", remote_path);
+    let _ = fs::remove_file(&patch).await;
+    fetch(mirrors, &remote_path, &patch, 0, 0, events).await?;
+    // 应用补丁时生成完整的新文件
+    disk_space::check(Path::new(&temp), size)?;
+
+    let (old, patch_path, new) = (
+        PathBuf::from(db_path),
+        PathBuf::from(&patch),
+        PathBuf::from(&temp),
+    );
+    let expected = expected.clone();
+    let ok = tokio::task::spawn_blocking(move || {
+        db_patch::apply(&old, &patch_path, &new).map_err(|e| e.to_string())?;
+        db_check::matches(&new, &expected, true).map_err(|e| e.to_string())
+    })
+    .await??;
+    let _ = fs::remove_file(&patch).await;
+
+    if !ok {
+        let _ = fs::remove_file(&temp).await;
+        hash_cache::forget(Path::new(&temp));
+        return Err("补丁应用后校验失败".into());
+    }
+    fs::rename(&temp, db_path).await?;
+    hash_cache::rename(Path::new(&temp), Path::new(db_path));
+
+    events.publish(Event::Finished);
+    info!("差分更新完成");
+    Ok(true)
 }
 
-pub async fn start_download_db() -> Result<(), Box<dyn std::error::Error>> {
+pub async fn start_download_db(events: &EventBus) -> Result<(), Box<dyn std::error::Error>> {
+    let _active = download_control::controller().start();
     let auth_token = protocol::handle().await.unwrap();
-    let root_path = env::current_exe()
-        .expect("获取当前路径失败")
-        .parent()
-        .expect("获取父级目录")
-        .to_str()
-        .expect("转换为字符串失败")
-        .to_string();
-    let db_path = format!("{}{}", root_path, "/game/db/compact.sqlite3");
-
-    download::download(&format!("{}/compact.sqlite3", auth_token.domain), &db_path).await
+    trusted_domain::check(&auth_token.domain)?;
+    let db_path = db_check::db_path();
+    let (expected, size) = signed_digest(&auth_token).await?;
+    let mirrors = mirror::select(&auth_token, &auth_token.db_file).await;
+
+    match patch_db(&mirrors, &db_path, &expected, size, events).await {
+        Ok(true) => return Ok(()),
+        Ok(false) => {}
+        Err(e) if download_control::is_cancelled(e.as_ref()) => return Err(e),
+        Err(e) => warn!("差分更新失败，改为完整下载 {:?}", e),
+    }
+
+    // 局域网内其他启动器有相同版本时优先从它们下载，下载后同样校验摘要
+    let peers = lan_peer::peers(&auth_token.db_file, &expected);
+    if !peers.is_empty() {
+        info!("从局域网下载 {:?}", peers);
+        let res = download::download(
+            &peers,
+            lan_peer::shared_name(&auth_token.db_file),
+            &db_path,
+            Some(expected.clone()),
+            Some(size),
+            events,
+        )
+        .await;
+        match res {
+            Ok(()) => return Ok(()),
+            Err(e) if download_control::is_cancelled(e.as_ref()) => return Err(e),
+            Err(e) => warn!("局域网下载失败，改为从服务器下载 {:?}", e),
+        }
+    }
+
+    download::download(
+        &mirrors,
+        &auth_token.db_file,
+        &db_path,
+        Some(expected),
+        Some(size),
+        events,
+    )
+    .await
+}
+
+/// 从签名的更新清单中取得 DB 摘要和解压后的大小，写入任何文件之前确认清单可信
+async fn signed_digest(
+    auth_token: &protocol::AuthToken,
+) -> Result<(ExpectedDigest, u64), Box<dyn std::error::Error>> {
+    let manifest = manifest::fetch(&auth_token.domain, UPDATE_MANIFEST).await?;
+    let file = manifest
+        .find(&auth_token.db_file)
+        .ok_or_else(|| format!("更新清单中没有 {}", auth_token.db_file))?;
+    let expected = ExpectedDigest::parse(&file.hash)?;
+
+    // 令牌中的摘要必须与清单一致
+    if !auth_token.db_hash.is_empty() && ExpectedDigest::parse(&auth_token.db_hash)? != expected {
+        return Err("令牌中的 DB 摘要与更新清单不一致".into());
+    }
+    Ok((expected, file.size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::stub::{response, serve};
+    use crate::test_util::temp_file;
+
+    fn data(seed: u8) -> Vec<u8> {
+        (0..100u8).map(|i| i.wrapping_mul(seed)).collect()
+    }
+
+    #[tokio::test]
+    async fn resume_after_connection_drop() {
+        let data = data(3);
+        let stub = serve(vec![
+            // 检查是否支持分段下载
+            response("200 OK", &["Content-Length: 100"], b""),
+            // 只发送一半数据就断开
+            response(
+                "200 OK",
+                &["Content-Length: 100", "ETag: \"v1\""],
+                &data[..50],
+            ),
+            response(
+                "206 Partial Content",
+                &["Content-Range: bytes 50-99/100", "ETag: \"v1\""],
+                &data[50..],
+            ),
+        ]);
+        let filename = temp_file("download-resume");
+
+        let size = fetch(
+            std::slice::from_ref(&stub.url),
+            "file",
+            &filename,
+            0,
+            0,
+            &EventBus::new(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(size, 100);
+        assert_eq!(std::fs::read(&filename).unwrap(), data);
+
+        let requests = stub.requests();
+        assert_eq!(requests.len(), 3);
+        assert!(requests[2].contains("range: bytes=50-"));
+        assert!(requests[2].contains("if-range: \"v1\""));
+        let _ = std::fs::remove_file(&filename);
+    }
+
+    #[tokio::test]
+    async fn restart_when_file_changed() {
+        let (old, new) = (data(3), data(5));
+        let stub = serve(vec![
+            response("200 OK", &["Content-Length: 100"], b""),
+            response(
+                "200 OK",
+                &["Content-Length: 100", "ETag: \"v1\""],
+                &old[..50],
+            ),
+            // If-Range 不匹配，服务器返回完整的新文件
+            response("200 OK", &["ETag: \"v2\""], &new),
+        ]);
+        let filename = temp_file("download-restart");
+
+        fetch(
+            std::slice::from_ref(&stub.url),
+            "file",
+            &filename,
+            0,
+            0,
+            &EventBus::new(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(std::fs::read(&filename).unwrap(), new);
+        let _ = std::fs::remove_file(&filename);
+    }
 }
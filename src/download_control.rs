@@ -0,0 +1,131 @@
+use std::error::Error;
+use std::fmt;
+use std::sync::OnceLock;
+
+use tokio::sync::watch;
+use tracing::info;
+
+static CONTROLLER: OnceLock<DownloadController> = OnceLock::new();
+
+/// 下载任务的控制指令
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Run,
+    Pause,
+    /// 取消下载，`discard` 为 true 时删除已下载的部分
+    Cancel {
+        discard: bool,
+    },
+}
+
+/// 下载被取消
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "下载已取消")
+    }
+}
+
+impl Error for Cancelled {}
+
+/// 是否为取消下载导致的错误
+pub fn is_cancelled(error: &(dyn Error + 'static)) -> bool {
+    error.downcast_ref::<Cancelled>().is_some()
+}
+
+/// 暂停、恢复、取消下载，下载任务通过 watch 通道等待状态变化，无需轮询
+pub struct DownloadController {
+    command: watch::Sender<Command>,
+    active: watch::Sender<bool>,
+}
+
+/// 下载任务运行期间持有，结束时自动标记为空闲
+pub struct ActiveGuard;
+
+impl Drop for ActiveGuard {
+    fn drop(&mut self) {
+        controller().active.send_replace(false);
+    }
+}
+
+pub fn controller() -> &'static DownloadController {
+    CONTROLLER.get_or_init(|| DownloadController {
+        command: watch::Sender::new(Command::Run),
+        active: watch::Sender::new(false),
+    })
+}
+
+impl DownloadController {
+    /// 开始新的下载任务
+    pub fn start(&self) -> ActiveGuard {
+        self.command.send_replace(Command::Run);
+        self.active.send_replace(true);
+        ActiveGuard
+    }
+
+    pub fn is_active(&self) -> bool {
+        *self.active.borrow()
+    }
+
+    pub fn pause(&self) {
+        info!("暂停下载");
+        self.command.send_if_modified(|c| {
+            let changed = *c == Command::Run;
+            if changed {
+                *c = Command::Pause;
+            }
+            changed
+        });
+    }
+
+    pub fn resume(&self) {
+        info!("恢复下载");
+        self.command.send_if_modified(|c| {
+            let changed = *c == Command::Pause;
+            if changed {
+                *c = Command::Run;
+            }
+            changed
+        });
+    }
+
+    pub fn cancel(&self, discard: bool) {
+        info!("取消下载 discard: {}", discard);
+        self.command.send_replace(Command::Cancel { discard });
+    }
+
+    /// 当前的取消指令
+    pub fn cancelled(&self) -> Option<bool> {
+        match *self.command.borrow() {
+            Command::Cancel { discard } => Some(discard),
+            _ => None,
+        }
+    }
+
+    /// 暂停时等待恢复，已取消时返回错误
+    pub async fn checkpoint(&self) -> Result<(), Cancelled> {
+        let mut rx = self.command.subscribe();
+        let command = *rx
+            .wait_for(|c| *c != Command::Pause)
+            .await
+            .map_err(|_| Cancelled)?;
+        match command {
+            Command::Cancel { .. } => Err(Cancelled),
+            _ => Ok(()),
+        }
+    }
+
+    /// 等待取消指令
+    pub async fn wait_cancel(&self) {
+        let mut rx = self.command.subscribe();
+        let _ = rx.wait_for(|c| matches!(c, Command::Cancel { .. })).await;
+    }
+
+    /// 等待当前下载任务结束
+    pub async fn wait_idle(&self) {
+        let mut rx = self.active.subscribe();
+        let _ = rx.wait_for(|active| !*active).await;
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use reqwest::header::{HeaderMap, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+use crate::{helper, http, local_source};
+
+const FILE_NAME: &str = "download_meta.json";
+
+/// 下载文件时服务器返回的 `ETag` / `Last-Modified`，以及保存时本地文件的大小和修改时间
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Record {
+    pub url: String,
+    #[serde(default)]
+    pub etag: Option<String>,
+    #[serde(default)]
+    pub last_modified: Option<String>,
+    #[serde(default)]
+    local: Option<(u64, u64)>,
+}
+
+impl Record {
+    fn from_headers(url: &str, headers: &HeaderMap) -> Option<Self> {
+        let header = |name| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string)
+        };
+        let record = Record {
+            url: url.to_string(),
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+            local: None,
+        };
+        (record.etag.is_some() || record.last_modified.is_some()).then_some(record)
+    }
+}
+
+type Meta = HashMap<String, Record>;
+
+fn file_path() -> PathBuf {
+    helper::data_dir().join(FILE_NAME)
+}
+
+fn load() -> Meta {
+    fs::read(file_path())
+        .ok()
+        .and_then(|data| serde_json::from_slice(&data).ok())
+        .unwrap_or_default()
+}
+
+fn key(filename: &str) -> String {
+    Path::new(filename).to_string_lossy().to_string()
+}
+
+/// 本地文件的大小和修改时间，文件被修改过时不再使用记录
+fn stat(filename: &str) -> Option<(u64, u64)> {
+    let metadata = fs::metadata(filename).ok()?;
+    let mtime = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some((metadata.len(), mtime.as_nanos() as u64))
+}
+
+fn save(meta: &Meta) {
+    let path = file_path();
+    let res = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&path, serde_json::to_vec(meta).unwrap_or_default()));
+    if let Err(e) = res {
+        warn!("保存下载记录失败 {:?}", e);
+    }
+}
+
+/// 下载完成后记录本地文件对应的服务器版本
+pub fn store(filename: &str, mut record: Record) {
+    record.local = stat(filename);
+    let mut meta = load();
+    meta.insert(key(filename), record);
+    save(&meta);
+}
+
+/// 可用于 `If-Range` 的服务器版本标识，弱 `ETag` 不能用于 `If-Range`
+pub fn validator(headers: &HeaderMap) -> Option<String> {
+    let header = |name| headers.get(name).and_then(|v| v.to_str().ok());
+    header(ETAG)
+        .filter(|etag| !etag.starts_with("W/"))
+        .or_else(|| header(LAST_MODIFIED))
+        .map(str::to_string)
+}
+
+/// 开始下载时记录服务器版本，续传未完成的文件时用 `If-Range` 确认文件没有变化
+pub fn store_partial(filename: &str, url: &str, validator: Option<String>) {
+    let mut meta = load();
+    match validator {
+        Some(validator) => {
+            let record = Record {
+                url: url.to_string(),
+                etag: Some(validator),
+                last_modified: None,
+                local: None,
+            };
+            meta.insert(key(filename), record);
+        }
+        None if meta.remove(&key(filename)).is_none() => return,
+        None => {}
+    }
+    save(&meta);
+}
+
+/// 续传未完成的文件时 `If-Range` 的值，没有记录时返回 None。
+/// 换了镜像时记录的版本标识属于另一台服务器，不能使用
+pub fn if_range(filename: &str, url: &str) -> Option<String> {
+    load()
+        .remove(&key(filename))
+        .filter(|record| record.url == url)
+        .and_then(|record| record.etag)
+}
+
+/// 文件下载完成后删除未完成时的记录
+pub fn forget(filename: &str) {
+    let mut meta = load();
+    if meta.remove(&key(filename)).is_some() {
+        save(&meta);
+    }
+}
+
+/// 条件请求的结果
+pub enum Check {
+    /// 服务器返回 304，本地文件就是最新版本
+    NotModified,
+    /// 需要下载，附带服务器当前版本的标识，下载成功后保存
+    Modified(Option<Record>),
+}
+
+/// 用 `If-None-Match` / `If-Modified-Since` 询问服务器文件是否变化
+pub async fn check(mirrors: &[String], remote_path: &str, filename: &str) -> Check {
+    let previous = load()
+        .remove(&key(filename))
+        .filter(|r| r.local.is_some() && r.local == stat(filename));
+
+    // 不同镜像的 ETag 可能不同，优先询问上次下载用的地址
+    let url = previous
+        .as_ref()
+        .map(|r| r.url.clone())
+        .filter(|url| {
+            mirrors
+                .iter()
+                .any(|m| *url == format!("{}/{}", m, remote_path))
+        })
+        .or_else(|| mirrors.first().map(|m| format!("{}/{}", m, remote_path)));
+    let Some(url) = url else {
+        return Check::Modified(None);
+    };
+
+    if let Some(source) = local_source::path(&url) {
+        return check_local(&url, &source, previous);
+    }
+
+    let mut request = http::client().head(&url);
+    if let Some(previous) = previous.as_ref().filter(|r| r.url == url) {
+        if let Some(etag) = &previous.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &previous.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+
+    match request.send().await {
+        Ok(response) if response.status() == StatusCode::NOT_MODIFIED => {
+            info!("服务器文件未变化 {}", url);
+            Check::NotModified
+        }
+        Ok(response) if response.status().is_success() => {
+            debug!("条件请求结果 {} {}", url, response.status());
+            Check::Modified(Record::from_headers(&url, response.headers()))
+        }
+        Ok(response) => {
+            debug!("条件请求结果 {} {}", url, response.status());
+            Check::Modified(None)
+        }
+        Err(e) => {
+            warn!("条件请求失败 {} {}", url, e);
+            Check::Modified(None)
+        }
+    }
+}
+
+/// 本地更新源没有 `ETag`，用源文件的大小和修改时间代替
+fn check_local(url: &str, source: &Path, previous: Option<Record>) -> Check {
+    let Some(version) = local_source::version(source) else {
+        return Check::Modified(None);
+    };
+    if previous.is_some_and(|r| r.url == url && r.etag.as_ref() == Some(&version)) {
+        info!("本地更新源文件未变化 {}", url);
+        return Check::NotModified;
+    }
+    Check::Modified(Some(Record {
+        url: url.to_string(),
+        etag: Some(version),
+        last_modified: None,
+        local: None,
+    }))
+}
@@ -0,0 +1,125 @@
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{debug, error, info};
+
+use crate::progress::Progress;
+use crate::MessageActions;
+
+/// 每个订阅者最多缓存的事件数，处理不过来时丢弃最旧的
+const CAPACITY: usize = 64;
+
+/// 后台任务发给界面、日志等订阅者的事件
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// 启动流程进入新的阶段
+    Stage(&'static str),
+    /// 下载或校验进度
+    Progress(Progress),
+    /// 下载或校验结束
+    Finished,
+    /// 需要提示用户的消息：标题、内容、按钮动作
+    Message(String, String, MessageActions),
+    /// 后台任务出错
+    Error(String),
+    /// 游戏已启动，进程 ID
+    GameStarted(u32),
+    /// 游戏已退出，退出码
+    GameExited(Option<i32>),
+}
+
+/// 事件总线，克隆后共用同一个通道，所有订阅者都会收到每个事件
+#[derive(Clone)]
+pub struct EventBus {
+    sender: Arc<Mutex<Option<broadcast::Sender<Event>>>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CAPACITY);
+        EventBus {
+            sender: Arc::new(Mutex::new(Some(sender))),
+        }
+    }
+
+    /// 发送事件，不等待订阅者处理；没有订阅者或已关闭时丢弃
+    pub fn publish(&self, event: Event) {
+        if let Some(sender) = self.sender.lock().unwrap().as_ref() {
+            let _ = sender.send(event);
+        }
+    }
+
+    /// 订阅之后发送的事件
+    pub fn subscribe(&self) -> Subscriber {
+        let receiver = match self.sender.lock().unwrap().as_ref() {
+            Some(sender) => sender.subscribe(),
+            // 已关闭，返回一个立即结束的订阅
+            None => broadcast::channel(1).1,
+        };
+        Subscriber(receiver)
+    }
+
+    /// 关闭总线，订阅者处理完已发送的事件后结束
+    pub fn shutdown(&self) {
+        if self.sender.lock().unwrap().take().is_some() {
+            debug!("事件总线已关闭");
+        }
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct Subscriber(broadcast::Receiver<Event>);
+
+impl Subscriber {
+    /// 下一个事件，总线关闭后返回 None
+    pub async fn recv(&mut self) -> Option<Event> {
+        loop {
+            match self.0.recv().await {
+                Ok(event) => return Some(event),
+                Err(RecvError::Lagged(count)) => debug!("跳过 {} 个事件", count),
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+/// 把事件写入日志
+pub async fn log(mut events: Subscriber) {
+    while let Some(event) = events.recv().await {
+        match event {
+            Event::Stage(stage) => info!("启动阶段: {}", stage),
+            Event::Progress(progress) => debug!("进度: {}", progress),
+            Event::Finished => info!("任务完成"),
+            Event::Message(title, content, _) => info!("消息 {}: {}", title, content),
+            Event::Error(e) => error!("任务出错 {}", e),
+            Event::GameStarted(pid) => info!("游戏已启动 pid {}", pid),
+            Event::GameExited(code) => info!("游戏已退出 {:?}", code),
+        }
+    }
+}
+
+/// 在控制台输出事件，从终端运行时用启动参数 `--console` 开启
+pub async fn console(mut events: Subscriber) {
+    while let Some(event) = events.recv().await {
+        match event {
+            Event::Stage(stage) => println!("[阶段] {}", stage),
+            Event::Progress(progress) => match progress.percentage() {
+                Some(percentage) => println!("[进度] {:.1}% {}", percentage, progress),
+                None => println!("[进度] {}", progress),
+            },
+            Event::Finished => println!("[完成]"),
+            Event::Message(title, content, _) => println!("[{}] {}", title, content),
+            Event::Error(e) => eprintln!("[错误] {}", e),
+            Event::GameStarted(pid) => println!("[游戏] 已启动 pid {}", pid),
+            Event::GameExited(code) => match code {
+                Some(code) => println!("[游戏] 已退出 {}", code),
+                None => println!("[游戏] 已退出"),
+            },
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// 客户端目录中必须存在的文件
+const REQUIRED_FILES: [&str; 2] = ["bin32\\archeage.exe", "game_pak"];
+
+/// 客户端目录中必须存在的目录
+const REQUIRED_DIRS: [&str; 1] = ["game\\db"];
+
+/// bin32 下通常存在的运行库，不同版本的客户端可能不同，缺少时只提示不阻止启动
+const EXPECTED_DLLS: [&str; 3] = ["CrySystem.dll", "Cry3DEngine.dll", "CryRenderD3D10.dll"];
+
+/// 游戏目录校验结果
+#[derive(Debug)]
+pub struct LayoutReport {
+    pub root: PathBuf,
+    pub missing_files: Vec<String>,
+    pub missing_dirs: Vec<String>,
+    /// 只作为提示，不影响 `is_ok`
+    pub missing_dlls: Vec<String>,
+}
+
+impl LayoutReport {
+    pub fn is_ok(&self) -> bool {
+        self.missing_files.is_empty() && self.missing_dirs.is_empty()
+    }
+}
+
+impl fmt::Display for LayoutReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_ok() {
+            write!(f, "游戏目录正常: {}", self.root.display())?;
+        } else {
+            write!(f, "目录 {} 缺少:", self.root.display())?;
+            for name in self.missing_files.iter().chain(self.missing_dirs.iter()) {
+                write!(f, " {}", name)?;
+            }
+        }
+        if !self.missing_dlls.is_empty() {
+            write!(f, "\n未找到运行库: {}", self.missing_dlls.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+/// 检查目录是否为完整的客户端目录
+pub fn validate(root: &Path) -> LayoutReport {
+    let missing_files = REQUIRED_FILES
+        .iter()
+        .filter(|name| !root.join(name).is_file())
+        .map(|name| name.to_string())
+        .collect();
+
+    let missing_dirs = REQUIRED_DIRS
+        .iter()
+        .filter(|name| !root.join(name).is_dir())
+        .map(|name| name.to_string())
+        .collect();
+
+    let missing_dlls = EXPECTED_DLLS
+        .iter()
+        .filter(|name| !root.join("bin32").join(name).is_file())
+        .map(|name| format!("bin32\\{}", name))
+        .collect();
+
+    LayoutReport {
+        root: root.to_path_buf(),
+        missing_files,
+        missing_dirs,
+        missing_dlls,
+    }
+}
@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+use crate::db_check::HashAlgorithm;
+use crate::{db_check, helper};
+
+const FILE_NAME: &str = "hash_cache.json";
+
+/// 上一次计算的文件摘要，文件大小和修改时间不变时直接复用
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct CacheEntry {
+    size: u64,
+    mtime: u64,
+    digest: String,
+}
+
+type Cache = HashMap<String, CacheEntry>;
+
+fn file_path() -> PathBuf {
+    helper::data_dir().join(FILE_NAME)
+}
+
+fn load() -> Cache {
+    fs::read(file_path())
+        .ok()
+        .and_then(|data| serde_json::from_slice(&data).ok())
+        .unwrap_or_default()
+}
+
+fn save(cache: &Cache) -> Result<(), Box<dyn std::error::Error>> {
+    let path = file_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, serde_json::to_vec(cache)?)?;
+    Ok(())
+}
+
+fn stat(path: &Path) -> Result<(u64, u64), Box<dyn std::error::Error>> {
+    let metadata = fs::metadata(path)?;
+    let mtime = metadata.modified()?.duration_since(UNIX_EPOCH)?.as_nanos() as u64;
+    Ok((metadata.len(), mtime))
+}
+
+fn key(path: &Path, algorithm: HashAlgorithm) -> String {
+    format!("{}:{}", algorithm, path.to_string_lossy())
+}
+
+/// 取出缓存中属于 `path` 的条目，返回 (算法名, 条目)
+fn take(cache: &mut Cache, path: &Path) -> Vec<(String, CacheEntry)> {
+    let path = path.to_string_lossy();
+    let keys: Vec<String> = cache
+        .keys()
+        .filter(|k| k.split_once(':').is_some_and(|(_, p)| p == path))
+        .cloned()
+        .collect();
+    keys.into_iter()
+        .filter_map(|k| {
+            let entry = cache.remove(&k)?;
+            let (algorithm, _) = k.split_once(':')?;
+            Some((algorithm.to_string(), entry))
+        })
+        .collect()
+}
+
+/// 文件改名后把缓存条目转到新路径下，避免留下临时文件的条目
+pub fn rename(from: &Path, to: &Path) {
+    let mut cache = load();
+    let entries = take(&mut cache, from);
+    if entries.is_empty() {
+        return;
+    }
+    // 改名不改变大小和修改时间，不一致说明文件已被替换
+    if let Ok((size, mtime)) = stat(to) {
+        for (algorithm, entry) in entries {
+            if entry.size == size && entry.mtime == mtime {
+                cache.insert(format!("{}:{}", algorithm, to.to_string_lossy()), entry);
+            }
+        }
+    }
+    if let Err(e) = save(&cache) {
+        warn!("写入摘要缓存失败 {:?}", e);
+    }
+}
+
+/// 删除文件后移除其缓存条目
+pub fn forget(path: &Path) {
+    let mut cache = load();
+    if take(&mut cache, path).is_empty() {
+        return;
+    }
+    if let Err(e) = save(&cache) {
+        warn!("写入摘要缓存失败 {:?}", e);
+    }
+}
+
+/// 获取文件摘要；`force` 为 true 时忽略缓存重新计算
+pub fn hash(
+    path: &Path,
+    algorithm: HashAlgorithm,
+    force: bool,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let key = key(path, algorithm);
+    let (size, mtime) = stat(path)?;
+    let mut cache = load();
+
+    if !force {
+        if let Some(entry) = cache.get(&key) {
+            if entry.size == size && entry.mtime == mtime {
+                debug!("使用缓存的摘要 {}", key);
+                return Ok(entry.digest.clone());
+            }
+        }
+    }
+
+    info!("计算文件摘要 {}", key);
+    let digest = db_check::hash_file(path, algorithm)?;
+
+    // 计算期间文件被修改则不写入缓存
+    if stat(path)? == (size, mtime) {
+        cache.insert(
+            key,
+            CacheEntry {
+                size,
+                mtime,
+                digest: digest.clone(),
+            },
+        );
+        if let Err(e) = save(&cache) {
+            warn!("写入摘要缓存失败 {:?}", e);
+        }
+    }
+
+    Ok(digest)
+}
@@ -0,0 +1,59 @@
+use std::env;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{game_check, launcher_config};
+
+/// 启动器所在目录
+pub fn exe_dir() -> PathBuf {
+    env::current_exe()
+        .expect("获取当前路径失败")
+        .parent()
+        .expect("获取父级目录")
+        .to_path_buf()
+}
+
+/// 当前 Unix 时间（秒），用于报告文件名和内容
+pub fn timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// 启动器数据目录（配置、缓存等），优先使用 %LOCALAPPDATA%\PLAA
+#[cfg(not(test))]
+pub fn data_dir() -> PathBuf {
+    match env::var("LOCALAPPDATA") {
+        Ok(local) => PathBuf::from(local).join("PLAA"),
+        Err(_) => exe_dir(),
+    }
+}
+
+#[cfg(test)]
+pub fn data_dir() -> PathBuf {
+    crate::test_util::data_dir()
+}
+
+/// 命令行参数 `--name=value` 的值，只有 `--name` 时为空字符串
+pub fn flag(name: &str) -> Option<String> {
+    let name = format!("--{}", name);
+    env::args()
+        .skip(1)
+        .find_map(|arg| match arg.strip_prefix(&name) {
+            Some("") => Some(String::new()),
+            Some(value) => value.strip_prefix('=').map(str::to_string),
+            None => None,
+        })
+}
+
+/// 游戏根目录：优先使用用户选择并通过校验的目录，否则为启动器所在目录
+pub fn game_root() -> PathBuf {
+    if let Some(path) = launcher_config::load().game_path {
+        let path = PathBuf::from(path);
+        if game_check::validate(&path).is_ok() {
+            return path;
+        }
+    }
+    exe_dir()
+}
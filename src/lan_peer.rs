@@ -0,0 +1,456 @@
+use std::collections::HashMap;
+use std::io::SeekFrom;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::fs::File;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tracing::{debug, info, warn};
+
+use crate::db_check::ExpectedDigest;
+use crate::launcher_config;
+
+/// 局域网广播端口，同一台电脑上的多个启动器共用
+const DISCOVERY_PORT: u16 = 29871;
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(5);
+/// 超过这个时间没有收到广播的启动器视为已离线
+const PEER_TIMEOUT: Duration = Duration::from_secs(15);
+/// 最多记录的启动器数量，广播没有认证，不能无限增长
+const MAX_PEERS: usize = 64;
+const MAGIC: &str = "PLAA-LAN/1";
+/// 请求头的最大长度
+const MAX_HEADER: usize = 8 * 1024;
+
+static LAN: OnceLock<Arc<Lan>> = OnceLock::new();
+
+/// 局域网共享的状态：本机共享的文件和发现的其他启动器
+struct Lan {
+    /// 区分同一台电脑上的多个启动器，忽略自己的广播
+    id: u64,
+    /// 远程路径 -> 本地文件和摘要，只有校验通过的文件才会共享
+    shared: Mutex<HashMap<String, Shared>>,
+    peers: Mutex<HashMap<SocketAddr, Peer>>,
+}
+
+struct Shared {
+    path: PathBuf,
+    digest: String,
+}
+
+struct Peer {
+    files: HashMap<String, String>,
+    seen: Instant,
+}
+
+/// 广播内容
+#[derive(Serialize, Deserialize)]
+struct Announcement {
+    magic: String,
+    id: u64,
+    port: u16,
+    /// 远程路径 -> `算法:十六进制`
+    files: HashMap<String, String>,
+}
+
+impl Lan {
+    fn new() -> Self {
+        Lan {
+            id: rand::random(),
+            shared: Mutex::new(HashMap::new()),
+            peers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn share(&self, remote_path: &str, path: &Path, expected: &ExpectedDigest) {
+        let remote_path = shared_name(remote_path);
+        debug!("局域网共享文件 {} {:?}", remote_path, path);
+        self.shared.lock().unwrap().insert(
+            remote_path.to_string(),
+            Shared {
+                path: path.to_path_buf(),
+                digest: expected.to_string(),
+            },
+        );
+    }
+
+    fn shared_path(&self, remote_path: &str) -> Option<PathBuf> {
+        self.shared
+            .lock()
+            .unwrap()
+            .get(remote_path)
+            .map(|shared| shared.path.clone())
+    }
+
+    /// 远程路径 -> 摘要，用于广播
+    fn files(&self) -> HashMap<String, String> {
+        self.shared
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(remote, shared)| (remote.clone(), shared.digest.clone()))
+            .collect()
+    }
+
+    fn peers(&self, remote_path: &str, expected: &ExpectedDigest) -> Vec<String> {
+        let (remote_path, digest) = (shared_name(remote_path), expected.to_string());
+        let mut peers = self.peers.lock().unwrap();
+        peers.retain(|_, peer| peer.seen.elapsed() < PEER_TIMEOUT);
+        peers
+            .iter()
+            .filter(|(_, peer)| peer.files.get(remote_path) == Some(&digest))
+            .map(|(addr, _)| format!("http://{}", addr))
+            .collect()
+    }
+
+    /// 记录其他启动器的广播，已满时先清理离线的启动器，仍然满时忽略新的启动器
+    fn receive(&self, from: SocketAddr, data: &[u8]) {
+        let Ok(announcement) = serde_json::from_slice::<Announcement>(data) else {
+            return;
+        };
+        if announcement.magic != MAGIC || announcement.id == self.id {
+            return;
+        }
+
+        let addr = SocketAddr::new(from.ip(), announcement.port);
+        let mut peers = self.peers.lock().unwrap();
+        if !peers.contains_key(&addr) {
+            if peers.len() >= MAX_PEERS {
+                peers.retain(|_, peer| peer.seen.elapsed() < PEER_TIMEOUT);
+            }
+            if peers.len() >= MAX_PEERS {
+                debug!("局域网启动器过多，忽略 {}", addr);
+                return;
+            }
+            info!("发现局域网启动器 {} {:?}", addr, announcement.files);
+        }
+        peers.insert(
+            addr,
+            Peer {
+                files: announcement.files,
+                seen: Instant::now(),
+            },
+        );
+    }
+}
+
+fn lan() -> &'static Arc<Lan> {
+    LAN.get_or_init(|| Arc::new(Lan::new()))
+}
+
+/// 开启局域网共享时启动文件服务和广播，重复调用只启动一次
+pub fn start() {
+    if !launcher_config::load().lan_sharing {
+        return;
+    }
+    static STARTED: OnceLock<()> = OnceLock::new();
+    if STARTED.set(()).is_err() {
+        return;
+    }
+
+    tokio::spawn(async {
+        if let Err(e) = run().await {
+            warn!("局域网共享启动失败 {:?}", e);
+        }
+    });
+}
+
+async fn run() -> std::io::Result<()> {
+    let target = SocketAddrV4::new(Ipv4Addr::BROADCAST, DISCOVERY_PORT).into();
+    let port = spawn(lan().clone(), discovery_socket()?, target).await?;
+    info!("局域网共享已启动，文件服务端口 {}", port);
+    Ok(())
+}
+
+/// 启动文件服务、接收广播和定时广播，返回文件服务端口
+async fn spawn(lan: Arc<Lan>, socket: UdpSocket, target: SocketAddr) -> std::io::Result<u16> {
+    let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    let port = listener.local_addr()?.port();
+    let socket = Arc::new(socket);
+
+    tokio::spawn(serve(lan.clone(), listener));
+    tokio::spawn(listen(lan.clone(), socket.clone()));
+    tokio::spawn(announce(lan, socket, port, target));
+    Ok(port)
+}
+
+/// 允许多个启动器绑定同一个广播端口
+fn discovery_socket() -> std::io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    socket.set_broadcast(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, DISCOVERY_PORT).into())?;
+    UdpSocket::from_std(socket.into())
+}
+
+/// 共享的是解压后的文件，名称去掉压缩扩展名，从其他启动器下载时按这个名称请求，不再解压
+pub fn shared_name(remote_path: &str) -> &str {
+    let lower = remote_path.to_ascii_lowercase();
+    [".zst", ".gz"]
+        .iter()
+        .find(|ext| lower.ends_with(*ext))
+        .map_or(remote_path, |ext| {
+            &remote_path[..remote_path.len() - ext.len()]
+        })
+}
+
+/// 把校验通过的文件加入共享
+pub fn share(remote_path: &str, path: &Path, expected: &ExpectedDigest) {
+    lan().share(remote_path, path, expected);
+}
+
+/// 拥有相同摘要文件的其他启动器地址，可以直接作为镜像使用，
+/// 请求时使用 [`shared_name`] 的名称
+pub fn peers(remote_path: &str, expected: &ExpectedDigest) -> Vec<String> {
+    lan().peers(remote_path, expected)
+}
+
+/// 定时广播本机共享的文件
+async fn announce(lan: Arc<Lan>, socket: Arc<UdpSocket>, port: u16, target: SocketAddr) {
+    loop {
+        let files = lan.files();
+        if !files.is_empty() {
+            let announcement = Announcement {
+                magic: MAGIC.to_string(),
+                id: lan.id,
+                port,
+                files,
+            };
+            let data = serde_json::to_vec(&announcement).unwrap_or_default();
+            if let Err(e) = socket.send_to(&data, target).await {
+                debug!("局域网广播失败 {:?}", e);
+            }
+        }
+        tokio::time::sleep(ANNOUNCE_INTERVAL).await;
+    }
+}
+
+/// 接收其他启动器的广播
+async fn listen(lan: Arc<Lan>, socket: Arc<UdpSocket>) {
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        match socket.recv_from(&mut buf).await {
+            Ok((len, from)) => lan.receive(from, &buf[..len]),
+            Err(e) => debug!("接收局域网广播失败 {:?}", e),
+        }
+    }
+}
+
+/// 简单的 HTTP 文件服务，只提供共享的文件，支持 `HEAD` 和 `Range`
+async fn serve(lan: Arc<Lan>, listener: TcpListener) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, addr)) => {
+                let lan = lan.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = respond(&lan, stream).await {
+                        debug!("局域网请求处理失败 {} {:?}", addr, e);
+                    }
+                });
+            }
+            Err(e) => warn!("局域网连接失败 {:?}", e),
+        }
+    }
+}
+
+async fn respond(lan: &Lan, stream: TcpStream) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    let mut head = (&mut reader).take(MAX_HEADER as u64);
+    let mut request = String::new();
+    let mut range = None;
+    loop {
+        let mut line = String::new();
+        if head.read_line(&mut line).await? == 0 {
+            return Ok(());
+        }
+        if line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("range") {
+                range = value.trim().strip_prefix("bytes=").map(str::to_string);
+            }
+        }
+        request.push_str(&line);
+    }
+    let mut stream = reader.into_inner();
+
+    let mut parts = request.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let remote_path = parts.next().unwrap_or_default().trim_start_matches('/');
+    let path = lan.shared_path(remote_path);
+
+    let file = match path {
+        Some(path) if method == "GET" || method == "HEAD" => File::open(path).await.ok(),
+        _ => None,
+    };
+    let Some(mut file) = file else {
+        return stream
+            .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+            .await;
+    };
+    let length = file.metadata().await?.len();
+
+    // 只支持 `bytes=start-` 和 `bytes=start-end`
+    let range = range.and_then(|r| {
+        let (start, end) = r.split_once('-')?;
+        let start: u64 = start.parse().ok()?;
+        let end = match end {
+            "" => length.checked_sub(1)?,
+            end => end.parse::<u64>().ok()?.min(length.checked_sub(1)?),
+        };
+        (start <= end).then_some((start, end))
+    });
+    let (status, start, count) = match range {
+        Some((start, end)) => ("206 Partial Content", start, end - start + 1),
+        None => ("200 OK", 0, length),
+    };
+
+    let mut header = format!(
+        "HTTP/1.1 {}\r\nContent-Length: {}\r\nAccept-Ranges: bytes\r\nContent-Type: application/octet-stream\r\nConnection: close\r\n",
+        status, count
+    );
+    if range.is_some() {
+        header.push_str(&format!(
+            "Content-Range: bytes {}-{}/{}\r\n",
+            start,
+            start + count - 1,
+            length
+        ));
+    }
+    header.push_str("\r\n");
+    stream.write_all(header.as_bytes()).await?;
+
+    if method == "GET" {
+        file.seek(SeekFrom::Start(start)).await?;
+        tokio::io::copy(&mut file.take(count), &mut stream).await?;
+    }
+    stream.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::EventBus;
+    use crate::test_util::temp_file;
+    use crate::{db_check, download, http};
+
+    fn digest() -> ExpectedDigest {
+        ExpectedDigest::parse("md5:0123456789abcdef0123456789abcdef").unwrap()
+    }
+
+    async fn local_socket() -> UdpSocket {
+        UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap()
+    }
+
+    /// 启动两个互相广播的实例，`a` 共享文件，返回 `b` 发现的启动器地址
+    async fn discover(remote_path: &str, path: &Path, expected: &ExpectedDigest) -> Vec<String> {
+        let (a, b) = (Arc::new(Lan::new()), Arc::new(Lan::new()));
+        let (socket_a, socket_b) = (local_socket().await, local_socket().await);
+        let (addr_a, addr_b) = (
+            socket_a.local_addr().unwrap(),
+            socket_b.local_addr().unwrap(),
+        );
+        a.share(remote_path, path, expected);
+        spawn(a, socket_a, addr_b).await.unwrap();
+        spawn(b.clone(), socket_b, addr_a).await.unwrap();
+
+        for _ in 0..50 {
+            let peers = b.peers(remote_path, expected);
+            if !peers.is_empty() {
+                assert!(b.peers("game/other.pak", expected).is_empty());
+                return peers;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        Vec::new()
+    }
+
+    #[tokio::test]
+    async fn fetch_from_peer() {
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        let path = temp_file("lan-peer-shared");
+        std::fs::write(&path, &data).unwrap();
+
+        // 两个实例互相广播到对方的接收端口
+        let peers = discover("game/db.pak", Path::new(&path), &digest()).await;
+        assert_eq!(peers.len(), 1);
+
+        let url = format!("{}/game/db.pak", peers[0]);
+        let body = http::client().get(&url).send().await.unwrap();
+        assert_eq!(body.bytes().await.unwrap(), data);
+        let missing = http::client()
+            .get(format!("{}/game/other.pak", peers[0]))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(missing.status(), reqwest::StatusCode::NOT_FOUND);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn download_compressed_file_from_peer() {
+        // 服务器上是压缩文件，共享的是解压后的文件
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i % 241) as u8).collect();
+        let path = temp_file("lan-peer-decompressed");
+        std::fs::write(&path, &data).unwrap();
+        let hex = db_check::hash_file(Path::new(&path), db_check::HashAlgorithm::Sha256).unwrap();
+        let expected = ExpectedDigest::parse(&format!("sha256:{}", hex)).unwrap();
+
+        let remote_path = "game/compact.sqlite3.zst";
+        assert_eq!(shared_name(remote_path), "game/compact.sqlite3");
+        let peers = discover(remote_path, Path::new(&path), &expected).await;
+        assert_eq!(peers.len(), 1);
+
+        let filename = temp_file("lan-peer-download");
+        download::download(
+            &peers,
+            shared_name(remote_path),
+            &filename,
+            Some(expected),
+            Some(data.len() as u64),
+            &EventBus::new(),
+        )
+        .await
+        .unwrap();
+        assert!(std::fs::read(&filename).unwrap() == data);
+
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(&filename);
+    }
+
+    #[test]
+    fn peers_are_capped() {
+        let lan = Lan::new();
+        for port in 0..(MAX_PEERS as u16 * 2) {
+            let announcement = Announcement {
+                magic: MAGIC.to_string(),
+                id: u64::from(port) + 1,
+                port,
+                files: HashMap::new(),
+            };
+            let data = serde_json::to_vec(&announcement).unwrap();
+            lan.receive((Ipv4Addr::LOCALHOST, 1).into(), &data);
+        }
+        assert_eq!(lan.peers.lock().unwrap().len(), MAX_PEERS);
+
+        // 忽略自己的广播和无效数据
+        let lan = Lan::new();
+        let own = Announcement {
+            magic: MAGIC.to_string(),
+            id: lan.id,
+            port: 1,
+            files: HashMap::new(),
+        };
+        let data = serde_json::to_vec(&own).unwrap();
+        lan.receive((Ipv4Addr::LOCALHOST, 1).into(), &data);
+        lan.receive((Ipv4Addr::LOCALHOST, 1).into(), b"garbage");
+        assert!(lan.peers.lock().unwrap().is_empty());
+    }
+}
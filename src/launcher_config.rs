@@ -0,0 +1,110 @@
+use std::fs;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::helper;
+
+const FILE_NAME: &str = "launcher.json";
+
+/// 启动器本地配置，保存在数据目录下
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LauncherConfig {
+    /// 用户手动选择的游戏目录
+    #[serde(default)]
+    pub game_path: Option<String>,
+    /// 下载代理，例如 `http://127.0.0.1:7890` 或 `socks5://127.0.0.1:1080`
+    #[serde(default)]
+    pub proxy: Option<String>,
+    /// 分段下载的最大连接数，1 表示只用单个连接
+    #[serde(default = "default_connections")]
+    pub connections: u32,
+    /// 下载限速 KB/s，0 表示不限速
+    #[serde(default)]
+    pub speed_limit_kb: u64,
+    /// 额外的更新源，与服务器镜像一起测速选择，
+    /// 可以是 `file://` 地址或本地、共享目录，例如 `\\server\aaemu`
+    #[serde(default)]
+    pub sources: Vec<String>,
+    /// 局域网共享：向其他启动器提供已校验的文件，并优先从它们下载
+    #[serde(default)]
+    pub lan_sharing: bool,
+    /// 除内置域名外信任的下载域名，令牌中的其他域名会被拒绝
+    #[serde(default)]
+    pub trusted_domains: Vec<String>,
+    /// 日志级别：error、warn、info、debug、trace，可用命令行 `--log-level=` 覆盖
+    #[serde(default = "default_log_level")]
+    pub log_level: String,
+    /// 日志保留天数，可用命令行 `--log-retention=` 覆盖
+    #[serde(default = "default_log_retention_days")]
+    pub log_retention_days: usize,
+    /// 问题反馈的提交地址，相对令牌中的域名
+    #[serde(default = "default_bug_report_path")]
+    pub bug_report_path: String,
+}
+
+fn default_connections() -> u32 {
+    4
+}
+
+fn default_log_level() -> String {
+    String::from("info")
+}
+
+fn default_log_retention_days() -> usize {
+    7
+}
+
+fn default_bug_report_path() -> String {
+    String::from("api/launcher/report")
+}
+
+impl Default for LauncherConfig {
+    fn default() -> Self {
+        LauncherConfig {
+            game_path: None,
+            proxy: None,
+            connections: default_connections(),
+            speed_limit_kb: 0,
+            sources: Vec::new(),
+            lan_sharing: false,
+            trusted_domains: Vec::new(),
+            log_level: default_log_level(),
+            log_retention_days: default_log_retention_days(),
+            bug_report_path: default_bug_report_path(),
+        }
+    }
+}
+
+fn file_path() -> PathBuf {
+    helper::data_dir().join(FILE_NAME)
+}
+
+pub fn load() -> LauncherConfig {
+    let path = file_path();
+    match fs::read(&path) {
+        Ok(data) => serde_json::from_slice(&data).unwrap_or_else(|e| {
+            warn!("配置文件解析失败，使用默认配置 {:?}", e);
+            LauncherConfig::default()
+        }),
+        Err(_) => LauncherConfig::default(),
+    }
+}
+
+pub fn save(config: &LauncherConfig) -> Result<(), Box<dyn std::error::Error>> {
+    let path = file_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, serde_json::to_vec_pretty(config)?)?;
+    info!("配置已保存 {:?}", path);
+    Ok(())
+}
+
+/// 读取、修改并保存配置
+pub fn update<F: FnOnce(&mut LauncherConfig)>(f: F) -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = load();
+    f(&mut config);
+    save(&config)
+}
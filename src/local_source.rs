@@ -0,0 +1,61 @@
+use std::io::{self, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use futures::Stream;
+use reqwest::Url;
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+/// 每次读取的字节数
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// `file://` 地址或本地、共享目录路径（如 `D:\update`、`\\server\share`）对应的本地路径，
+/// 其他地址返回 None
+pub fn path(url: &str) -> Option<PathBuf> {
+    if url
+        .get(..7)
+        .is_some_and(|s| s.eq_ignore_ascii_case("file://"))
+    {
+        return Url::parse(url).ok()?.to_file_path().ok();
+    }
+    if url.contains("://") {
+        return None;
+    }
+    let path = Path::new(url);
+    (path.is_absolute() || url.starts_with(r"\\")).then(|| path.to_path_buf())
+}
+
+pub fn is_local(url: &str) -> bool {
+    path(url).is_some()
+}
+
+/// 文件大小和修改时间组成的版本标识，作用与 HTTP 的 `ETag` 相同
+pub fn version(path: &Path) -> Option<String> {
+    let metadata = std::fs::metadata(path).ok()?;
+    let mtime = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some(format!("{}-{}", metadata.len(), mtime.as_nanos()))
+}
+
+/// 从 `offset` 开始按块读取文件
+pub async fn open(path: &Path, offset: u64) -> io::Result<impl Stream<Item = io::Result<Vec<u8>>>> {
+    let mut file = File::open(path).await?;
+    if offset > 0 {
+        file.seek(SeekFrom::Start(offset)).await?;
+    }
+
+    let stream = futures::stream::unfold(Some(file), |file| async move {
+        let mut file = file?;
+        let mut buf = vec![0; CHUNK_SIZE];
+        match file.read(&mut buf).await {
+            Ok(0) => None,
+            Ok(n) => {
+                buf.truncate(n);
+                Some((Ok(buf), Some(file)))
+            }
+            // 出错后结束数据流
+            Err(e) => Some((Err(e), None)),
+        }
+    });
+    Ok(stream)
+}
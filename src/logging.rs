@@ -0,0 +1,72 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use tracing::warn;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt;
+use tracing_subscriber::prelude::*;
+
+use crate::redact::Redacting;
+use crate::{helper, launcher_config};
+
+const FILE_PREFIX: &str = "launcher";
+const FILE_SUFFIX: &str = "log";
+
+/// 日志目录，位于数据目录下
+pub fn dir() -> PathBuf {
+    helper::data_dir().join("logs")
+}
+
+/// 初始化日志：按天滚动写入日志目录，保留最近若干天；调试版本同时输出到控制台。
+/// 所有输出都先去掉密码、令牌等敏感内容。
+pub fn init() {
+    let config = launcher_config::load();
+    // 命令行参数优先于配置文件
+    let level = helper::flag("log-level").unwrap_or(config.log_level);
+    let retention = helper::flag("log-retention")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(config.log_retention_days)
+        .max(1);
+    let level_filter = LevelFilter::from_str(&level).unwrap_or(LevelFilter::INFO);
+
+    let _ = std::fs::create_dir_all(dir());
+    let appender = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix(FILE_PREFIX)
+        .filename_suffix(FILE_SUFFIX)
+        .max_log_files(retention)
+        .build(dir());
+    let (file_layer, file_error) = match appender {
+        Ok(appender) => {
+            let layer = fmt::layer()
+                .with_ansi(false)
+                .with_writer(Redacting::new(appender))
+                .with_filter(level_filter);
+            (Some(layer), None)
+        }
+        Err(e) => (None, Some(e)),
+    };
+    let stdout_layer = cfg!(debug_assertions).then(|| {
+        fmt::layer()
+            .with_writer(Redacting::new(std::io::stdout))
+            .with_filter(level_filter)
+    });
+
+    tracing_subscriber::registry()
+        .with(file_layer)
+        .with(stdout_layer)
+        .init();
+
+    if let Some(e) = file_error {
+        warn!("无法创建日志文件 {:?} {}", dir(), e);
+    }
+}
+
+/// 在资源管理器中打开日志目录
+pub fn open_dir() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = dir();
+    std::fs::create_dir_all(&dir)?;
+    opener::open(&dir)?;
+    Ok(())
+}
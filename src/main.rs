@@ -1,11 +1,8 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use std::sync::atomic::AtomicBool;
-use std::sync::{Arc, OnceLock};
 use std::{env};
-use tokio::sync::mpsc::{channel, Sender};
-use tokio::sync::Mutex;
 use tracing::{debug, error, info, warn};
+use crate::events::EventBus;
 
 slint::include_modules!();
 
@@ -16,17 +13,43 @@ mod regedit;
 
 mod cipher;
 mod protocol;
+mod rate_limit;
+mod redact;
 
 mod helper;
+mod progress;
 
+mod bug_report;
 mod business_logic;
+mod client_repair;
+mod crash_report;
 mod db_check;
+mod db_patch;
+mod diagnostics;
+mod disk_space;
 mod download;
+mod download_control;
+mod download_meta;
+mod events;
+mod game_check;
+mod hash_cache;
+mod http;
+mod lan_peer;
+mod launcher_config;
+mod logging;
+mod local_source;
+mod manifest;
+mod mirror;
 mod system_config;
 
+mod segmented;
+mod signature;
 mod site_link_url;
 
 mod task;
+#[cfg(test)]
+mod test_util;
+mod trusted_domain;
 
 mod window;
 
@@ -36,43 +59,75 @@ const WEBSITE_URL: &str = "https://plaa.top";
 
 const VERSION: u16 = 3;
 
-static SENDER: OnceLock<Arc<Mutex<Sender<Task>>>> = OnceLock::new();
-static PAUSE_UPGRADE: OnceLock<Arc<AtomicBool>> = OnceLock::new();
-
-#[derive(Debug)]
-enum Task {
-    Progress(f64),
-    Message(String, String, MessageActions),
+/// 发布版没有控制台窗口，命令行输出前附加到父进程（命令提示符）的控制台
+fn attach_console() {
+    use windows::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+    // 不是从命令行启动时没有可附加的控制台，忽略错误
+    let _ = unsafe { AttachConsole(ATTACH_PARENT_PROCESS) };
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // initialize tracing
-    tracing_subscriber::fmt::init();
+    logging::init();
+    crash_report::install();
 
     debug!("debug");
     info!("info");
     warn!("warn");
     error!("error");
 
-    info!("程序启动...");
-
-    let (tx, mut rx) = channel::<Task>(1);
-    SENDER.set(Arc::new(Mutex::new(tx.clone()))).unwrap();
-
-    PAUSE_UPGRADE.set(Arc::new(AtomicBool::new(false))).unwrap();
-
-    let app = window::create()?;
-
+    info!("程序启动... 版本 {} 日志目录 {:?}", VERSION, logging::dir());
+
+    // `--diagnostics[=路径]` 只生成诊断报告，不打开窗口
+    if let Some(path) = helper::flag("diagnostics") {
+        attach_console();
+        let path = if path.is_empty() {
+            diagnostics::default_path()
+        } else {
+            path.into()
+        };
+        let result = tokio::task::spawn_blocking({
+            let path = path.clone();
+            move || diagnostics::create(&path, None).map_err(|e| e.to_string())
+        })
+        .await?;
+        match result {
+            Ok(()) => println!("诊断报告已保存到 {}", path.display()),
+            Err(e) => {
+                error!("生成诊断报告失败 {}", e);
+                eprintln!("生成诊断报告失败 {}", e);
+            }
+        }
+        return Ok(());
+    }
+
+    let events = EventBus::new();
+    let mut subscribers = vec![tokio::spawn(events::log(events.subscribe()))];
+    if helper::flag("console").is_some() {
+        attach_console();
+        subscribers.push(tokio::spawn(events::console(events.subscribe())));
+    }
+
+    let app = window::create(&events)?;
+
+    let ui = events.subscribe();
+    let bus = events.clone();
     slint::spawn_local(async move {
-        business_logic::handle(&app).await;
-        task::handle(&mut rx, &app).await;
+        business_logic::handle(&app, &bus).await;
+        task::handle(ui, &app).await;
     })
     .unwrap();
 
     // slint::run_event_loop_until_quit().unwrap();
     tokio::task::block_in_place(slint::run_event_loop).unwrap();
 
+    // 等待日志等订阅者处理完剩余的事件
+    events.shutdown();
+    for subscriber in subscribers {
+        let _ = subscriber.await;
+    }
+
     info!("程序结束");
     Ok(())
 }
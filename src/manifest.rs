@@ -0,0 +1,96 @@
+use serde::Deserialize;
+use tracing::info;
+
+use crate::{http, signature};
+
+/// 签名清单的十六进制公钥，构建时由环境变量 `PLAA_MANIFEST_PUBLIC_KEY` 提供，未设置时无法构建。
+/// 密钥对由服务器运维用 `sign-manifest keygen` 生成，私钥只保存在签名清单的机器上
+#[cfg(not(test))]
+const PUBLIC_KEY: &str = env!("PLAA_MANIFEST_PUBLIC_KEY");
+/// 测试使用 RFC 8032 的测试密钥
+#[cfg(test)]
+const PUBLIC_KEY: &str = "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a";
+
+/// 服务器提供的文件清单，必须附带有效签名
+#[derive(Deserialize, Debug)]
+pub struct Manifest {
+    /// 清单版本，仅用于日志
+    #[serde(default)]
+    pub version: String,
+    pub files: Vec<ManifestFile>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ManifestFile {
+    /// 相对路径，例如 `bin32/archeage.exe`
+    pub path: String,
+    pub size: u64,
+    /// 文件摘要，支持 `md5:`、`sha256:`、`blake3:` 前缀
+    pub hash: String,
+}
+
+impl Manifest {
+    pub fn find(&self, path: &str) -> Option<&ManifestFile> {
+        self.files.iter().find(|f| f.path == path)
+    }
+}
+
+/// 验证签名后解析清单，签名缺失或无效时返回错误
+pub fn parse(data: &[u8], signature: &str) -> Result<Manifest, Box<dyn std::error::Error>> {
+    signature::verify(PUBLIC_KEY, data, signature)?;
+    Ok(serde_json::from_slice(data)?)
+}
+
+/// 获取 `{domain}/{name}` 和签名文件 `{domain}/{name}.sig`
+pub async fn fetch(domain: &str, name: &str) -> Result<Manifest, Box<dyn std::error::Error>> {
+    let url = format!("{}/{}", domain, name);
+    let sig_url = format!("{}{}", url, signature::SUFFIX);
+    info!("获取清单 {}", url);
+
+    let (data, sig) = http::retry(|| async {
+        let get = |url: &str| http::client().get(url).send();
+        let data = get(&url).await?.error_for_status()?.bytes().await?;
+        let sig = get(&sig_url).await?.error_for_status()?.text().await?;
+        Ok((data, sig))
+    })
+    .await
+    .map_err(|e| format!("获取清单失败 {}: {}", url, e))?;
+
+    let manifest = parse(&data, &sig).map_err(|e| format!("清单 {} 校验失败: {}", url, e))?;
+    info!(
+        "清单签名有效 {} 版本 {} 文件数量 {}",
+        url,
+        manifest.version,
+        manifest.files.len()
+    );
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 与 [`PUBLIC_KEY`] 对应的私钥
+    const SECRET: &str = "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60";
+    const DATA: &[u8] = br#"{"version":"1","files":[{"path":"a","size":1,"hash":"md5:0"}]}"#;
+
+    #[test]
+    fn accept_valid_signature() {
+        assert_eq!(signature::public_key(SECRET).unwrap(), PUBLIC_KEY);
+        let manifest = parse(DATA, &signature::sign(SECRET, DATA).unwrap()).unwrap();
+        assert_eq!(manifest.version, "1");
+        assert!(manifest.find("a").is_some());
+    }
+
+    #[test]
+    fn reject_missing_or_invalid_signature() {
+        // 没有签名
+        assert!(parse(DATA, "").is_err());
+        // 其他密钥签名
+        let other = signature::sign(&"11".repeat(32), DATA).unwrap();
+        assert!(parse(DATA, &other).is_err());
+        // 内容被修改
+        let signed = signature::sign(SECRET, DATA).unwrap();
+        assert!(parse(br#"{"version":"2","files":[]}"#, &signed).is_err());
+    }
+}
@@ -0,0 +1,150 @@
+use std::time::{Duration, Instant};
+
+use futures::future::join_all;
+use tracing::{info, warn};
+
+use crate::protocol::AuthToken;
+use crate::{http, launcher_config, local_source, trusted_domain};
+
+const MIRROR_LIST: &str = "mirrors.json";
+
+/// 测速超时时间
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// 配置中的更新源、令牌中的下载域名和镜像，加上服务器镜像列表，去重后返回
+pub async fn list(auth_token: &AuthToken) -> Vec<String> {
+    let mut remote = vec![auth_token.domain.clone()];
+    remote.extend(auth_token.mirrors.iter().cloned());
+
+    let url = format!("{}/{}", auth_token.domain, MIRROR_LIST);
+    let res = async {
+        http::client()
+            .get(&url)
+            .timeout(PROBE_TIMEOUT)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Vec<String>>()
+            .await
+    }
+    .await;
+    match res {
+        Ok(list) => remote.extend(list),
+        Err(e) => info!("未获取到镜像列表 {}", e),
+    }
+
+    merge(launcher_config::load().sources, remote)
+}
+
+/// 令牌和服务器下发的地址必须是可信域名的 HTTPS 地址。
+/// 本地和共享目录只能来自本机配置：下发的地址可能被用来读取任意本地文件，
+/// 或通过 SMB 共享泄露 Windows 凭据
+fn merge(configured: Vec<String>, remote: Vec<String>) -> Vec<String> {
+    let remote = remote.into_iter().filter(|mirror| {
+        if local_source::is_local(mirror) {
+            warn!("忽略非本机配置的本地更新源 {}", mirror);
+            return false;
+        }
+        match trusted_domain::check(mirror) {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("忽略不可信的镜像 {} {}", mirror, e);
+                false
+            }
+        }
+    });
+
+    let mut unique: Vec<String> = Vec::new();
+    for mirror in configured.into_iter().chain(remote) {
+        let mirror = mirror.trim_end_matches('/').to_string();
+        if !mirror.is_empty() && !unique.contains(&mirror) {
+            unique.push(mirror);
+        }
+    }
+    unique
+}
+
+/// 对每个镜像请求一次 `path`，按响应时间排序，不可用的镜像排在最后
+pub async fn rank(mirrors: Vec<String>, path: &str) -> Vec<String> {
+    if mirrors.len() < 2 {
+        return mirrors;
+    }
+
+    let probes = mirrors.into_iter().map(|mirror| async move {
+        let start = Instant::now();
+        let url = format!("{}/{}", mirror, path);
+        if let Some(local) = local_source::path(&url) {
+            let latency = tokio::fs::metadata(&local)
+                .await
+                .is_ok()
+                .then(|| start.elapsed());
+            if latency.is_none() {
+                warn!("本地更新源缺少文件 {:?}", local);
+            }
+            return (mirror, latency);
+        }
+        let res = http::client()
+            .head(&url)
+            .timeout(PROBE_TIMEOUT)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status());
+        let latency = match res {
+            Ok(_) => Some(start.elapsed()),
+            Err(e) => {
+                warn!("镜像不可用 {} {}", mirror, e);
+                None
+            }
+        };
+        (mirror, latency)
+    });
+
+    let mut results = join_all(probes).await;
+    results.sort_by_key(|(_, latency)| latency.unwrap_or(Duration::MAX));
+    info!("镜像测速结果 {:?}", results);
+    results.into_iter().map(|(mirror, _)| mirror).collect()
+}
+
+/// 获取按速度排序的镜像列表
+pub async fn select(auth_token: &AuthToken, path: &str) -> Vec<String> {
+    rank(list(auth_token).await, path).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn local_sources_only_from_config() {
+        let configured = strings(&["file:///C:/update", r"\\server\share"]);
+        let remote = strings(&[
+            "https://plaa.top",
+            "file:///C:/Windows",
+            r"\\attacker\share",
+            "https://plaa.top/",
+        ]);
+        assert_eq!(
+            merge(configured, remote),
+            strings(&["file:///C:/update", r"\\server\share", "https://plaa.top"])
+        );
+    }
+
+    #[test]
+    fn remote_mirrors_must_be_trusted() {
+        let remote = strings(&[
+            "http://plaa.top",
+            "https://cdn.plaa.top/",
+            "https://plaa.top.example.com",
+            "https://example.com",
+        ]);
+        let configured = strings(&["http://192.168.1.2:8080"]);
+        assert_eq!(
+            merge(configured, remote),
+            strings(&["http://192.168.1.2:8080", "https://cdn.plaa.top"])
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// 两次进度通知的最小间隔
+const REPORT_INTERVAL: Duration = Duration::from_millis(200);
+
+/// 速度平滑系数，越小越平滑
+const SPEED_SMOOTHING: f64 = 0.3;
+
+/// 进度信息
+#[derive(Debug, Clone, Default)]
+pub struct Progress {
+    /// 已完成字节数
+    pub done: u64,
+    /// 总字节数，未知时为 None
+    pub total: Option<u64>,
+    /// 平滑后的速度 bytes/s
+    pub speed: f64,
+    /// 预计剩余时间
+    pub eta: Option<Duration>,
+}
+
+impl Progress {
+    /// 百分比，总大小未知时为 None
+    pub fn percentage(&self) -> Option<f64> {
+        self.total
+            .filter(|total| *total > 0)
+            .map(|total| (self.done as f64 / total as f64 * 100.0).min(100.0))
+    }
+}
+
+pub fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", value as u64, UNITS[unit])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+fn format_eta(eta: Duration) -> String {
+    let secs = eta.as_secs();
+    if secs < 60 {
+        format!("剩余 {} 秒", secs.max(1))
+    } else if secs < 3600 {
+        format!("剩余 {} 分钟", secs.div_ceil(60))
+    } else {
+        format!("剩余 {} 小时 {} 分钟", secs / 3600, secs % 3600 / 60)
+    }
+}
+
+/// 例如 `123.0 MB / 800.0 MB – 5.2 MB/s – 剩余 2 分钟`
+impl fmt::Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", format_bytes(self.done as f64))?;
+        if let Some(total) = self.total {
+            write!(f, " / {}", format_bytes(total as f64))?;
+        }
+        if self.speed > 0.0 {
+            write!(f, " – {}/s", format_bytes(self.speed))?;
+        }
+        if let Some(eta) = self.eta {
+            write!(f, " – {}", format_eta(eta))?;
+        }
+        Ok(())
+    }
+}
+
+/// 统计进度和速度，并限制通知频率
+pub struct Tracker {
+    progress: Progress,
+    last_report: Instant,
+    last_done: u64,
+}
+
+impl Tracker {
+    pub fn new(done: u64, total: Option<u64>) -> Self {
+        Tracker {
+            progress: Progress {
+                done,
+                total: total.filter(|total| *total > 0),
+                ..Progress::default()
+            },
+            last_report: Instant::now(),
+            last_done: done,
+        }
+    }
+
+    /// 增加已完成字节数，距上次通知超过间隔时返回最新进度
+    pub fn advance(&mut self, bytes: u64) -> Option<Progress> {
+        self.progress.done += bytes;
+
+        let elapsed = self.last_report.elapsed();
+        if elapsed < REPORT_INTERVAL {
+            return None;
+        }
+
+        let current = (self.progress.done - self.last_done) as f64 / elapsed.as_secs_f64();
+        self.progress.speed = if self.progress.speed > 0.0 {
+            self.progress.speed * (1.0 - SPEED_SMOOTHING) + current * SPEED_SMOOTHING
+        } else {
+            current
+        };
+        self.progress.eta = match self.progress.total {
+            Some(total) if self.progress.speed > 0.0 => Some(Duration::from_secs_f64(
+                total.saturating_sub(self.progress.done) as f64 / self.progress.speed,
+            )),
+            _ => None,
+        };
+
+        self.last_report = Instant::now();
+        self.last_done = self.progress.done;
+        Some(self.progress.clone())
+    }
+
+    pub fn done(&self) -> u64 {
+        self.progress.done
+    }
+}
@@ -1,5 +1,6 @@
 use std::env::args;
 
+use crate::redact::{self, Secret};
 use crate::WEBSITE_URL;
 use base64::engine::general_purpose;
 use base64::Engine;
@@ -12,7 +13,7 @@ pub struct AuthToken {
     #[serde(rename = "u")]
     pub username: String,
     #[serde(rename = "p")]
-    pub password: String,
+    pub password: Secret<String>,
     #[serde(rename = "s")]
     pub server: String,
     #[serde(rename = "P")]
@@ -21,17 +22,30 @@ pub struct AuthToken {
     pub launcher_version: u16,
     #[serde(rename = "mv")]
     pub with_launcher_version: u16,
+    /// DB 摘要，支持 `md5:`、`sha256:`、`blake3:` 前缀，无前缀时为 MD5
     #[serde(rename = "dh")]
     pub db_hash: String,
     #[serde(rename = "d")]
     #[serde(default = "domain")]
     pub domain: String,
+    /// DB 下载文件名，`.zst`/`.gz` 结尾时边下载边解压
+    #[serde(rename = "df")]
+    #[serde(default = "db_file")]
+    pub db_file: String,
+    /// 备用下载镜像，与 `d` 提供相同的文件
+    #[serde(rename = "m")]
+    #[serde(default)]
+    pub mirrors: Vec<String>,
 }
 
 pub fn domain() -> String {
     String::from(WEBSITE_URL)
 }
 
+pub fn db_file() -> String {
+    String::from("compact.sqlite3")
+}
+
 #[derive(Debug)]
 struct MyError {
     message: String,
@@ -62,7 +76,8 @@ pub async fn handle() -> Result<AuthToken, Box<dyn Error>> {
             return Err(MyError::new("请在官网点击“开始游戏”按钮。").into());
         }
         let args_vec: Vec<_> = args.into_iter().collect();
-        let schema = args_vec.get(1);
+        // 跳过 `--log-level=` 等启动器参数
+        let schema = args_vec.iter().skip(1).find(|arg| !arg.starts_with("--"));
 
         match schema {
             None => Err(MyError::new("协议参数无效").into()),
@@ -81,7 +96,7 @@ pub async fn handle() -> Result<AuthToken, Box<dyn Error>> {
                 let iv = data.get(0..8).expect("IV获取失败");
                 let ciphertext = data.get(8..).expect("密文获取失败");
 
-                tracing::info!("协议内容 {}", url);
+                tracing::info!("协议内容 {}", redact::redact(url));
                 let plaintext =
                     super::cipher::decrypt(ciphertext, <&[u8; 8]>::try_from(iv).unwrap())?;
                 let auth_token: AuthToken = serde_json::from_slice(plaintext.as_slice())
@@ -91,3 +106,20 @@ pub async fn handle() -> Result<AuthToken, Box<dyn Error>> {
         }
     // });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn debug_hides_password() {
+        let json = r#"{"u":"player","p":"hunter2-secret","s":"127.0.0.1","P":1239,"v":3,"mv":3,"dh":""}"#;
+        let token: AuthToken = serde_json::from_str(json).unwrap();
+        assert_eq!(token.password.expose(), "hunter2-secret");
+
+        let debug = format!("{:?}", token);
+        assert!(debug.contains("player"));
+        assert!(!debug.contains("hunter2-secret"));
+        assert!(!format!("{:#?}", token).contains("hunter2-secret"));
+    }
+}
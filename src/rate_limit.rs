@@ -0,0 +1,130 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use tokio::sync::Notify;
+use tracing::info;
+
+use crate::launcher_config;
+
+static LIMITER: OnceLock<RateLimiter> = OnceLock::new();
+
+/// 所有下载共用的限速器（令牌桶），限速值可在下载过程中修改
+pub struct RateLimiter {
+    /// bytes/s，0 表示不限速
+    limit: AtomicU64,
+    bucket: Mutex<Bucket>,
+    /// 限速值修改时唤醒正在等待的下载，按新的限速重新计算
+    changed: Notify,
+}
+
+struct Bucket {
+    /// 可用字节数，为负数时表示需要等待
+    available: f64,
+    last: Instant,
+}
+
+pub fn limiter() -> &'static RateLimiter {
+    LIMITER.get_or_init(|| RateLimiter {
+        limit: AtomicU64::new(launcher_config::load().speed_limit_kb * 1024),
+        bucket: Mutex::new(Bucket {
+            available: 0.0,
+            last: Instant::now(),
+        }),
+        changed: Notify::new(),
+    })
+}
+
+impl RateLimiter {
+    /// 设置限速 KB/s，0 表示不限速
+    pub fn set_limit_kb(&self, kb: u64) {
+        info!("下载限速 {} KB/s", kb);
+        self.limit.store(kb * 1024, Ordering::Relaxed);
+        self.changed.notify_waiters();
+    }
+
+    pub fn limit_kb(&self) -> u64 {
+        self.limit.load(Ordering::Relaxed) / 1024
+    }
+
+    /// 消耗 `bytes` 个字节的额度，超出限速时等待
+    pub async fn acquire(&self, bytes: u64) {
+        let mut bytes = bytes as f64;
+        loop {
+            // 先登记再读取限速值，避免错过读取之后的修改
+            let changed = self.changed.notified();
+            tokio::pin!(changed);
+            changed.as_mut().enable();
+
+            let limit = self.limit.load(Ordering::Relaxed);
+            if limit == 0 {
+                return;
+            }
+
+            let wait = {
+                let mut bucket = self.bucket.lock().unwrap();
+                let now = Instant::now();
+                let refill = now.duration_since(bucket.last).as_secs_f64() * limit as f64;
+                // 最多积累一秒的额度
+                bucket.available = (bucket.available + refill).min(limit as f64);
+                bucket.last = now;
+                // 只在第一次扣除，重新计算时等待的是同一笔欠额
+                bucket.available -= bytes;
+                bytes = 0.0;
+
+                if bucket.available < 0.0 {
+                    Duration::from_secs_f64(-bucket.available / limit as f64)
+                } else {
+                    Duration::ZERO
+                }
+            };
+
+            if wait.is_zero() {
+                return;
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(wait) => return,
+                _ = &mut changed => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_limit(kb: u64) -> RateLimiter {
+        RateLimiter {
+            limit: AtomicU64::new(kb * 1024),
+            bucket: Mutex::new(Bucket {
+                available: 0.0,
+                last: Instant::now(),
+            }),
+            changed: Notify::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn wake_on_limit_change() {
+        // 1 KB/s 下 10 KB 需要等待约十秒
+        let limiter = with_limit(1);
+        let start = Instant::now();
+        tokio::join!(limiter.acquire(10 * 1024), async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            limiter.set_limit_kb(0);
+        });
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn recompute_wait_on_limit_change() {
+        let limiter = with_limit(1);
+        let start = Instant::now();
+        tokio::join!(limiter.acquire(10 * 1024), async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            limiter.set_limit_kb(100 * 1024);
+        });
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,185 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::io::{self, Write};
+
+use serde::{Deserialize, Serialize};
+use tracing_subscriber::fmt::MakeWriter;
+use zeroize::Zeroize;
+
+/// 替换敏感内容的文本
+const MASK: &str = "******";
+
+/// 这些标记之后直到结束字符之前的内容都会被替换
+const MARKERS: &[(&str, &[char])] = &[
+    // 协议地址中的加密令牌
+    ("plaa://", &[' ', '"', '\'', '&']),
+    // 启动参数中的票据句柄
+    ("-handle ", &[' ', '"']),
+    // 票据 XML 和令牌 JSON 中的密码
+    ("<password>", &['<']),
+    ("\"p\":\"", &['"']),
+];
+
+/// 密码、密钥、令牌等敏感值，`Debug` 和 `Display` 只输出掩码，释放时清零内存
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Secret<T: Zeroize>(T);
+
+impl<T: Zeroize> Secret<T> {
+    /// 取得原始值，只在真正需要时使用，不要写入日志
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: Zeroize> Drop for Secret<T> {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl<T: Zeroize> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(MASK)
+    }
+}
+
+impl<T: Zeroize> fmt::Display for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(MASK)
+    }
+}
+
+/// 替换文本中已知格式的敏感字段。不登记运行时得到的原始值，
+/// 密码和令牌只保存在 `Secret` 中，释放时清零
+pub fn redact(text: &str) -> Cow<'_, str> {
+    let mut text = Cow::Borrowed(text);
+
+    for (marker, terminators) in MARKERS {
+        if !text.contains(marker) {
+            continue;
+        }
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text.as_ref();
+        while let Some(pos) = rest.find(marker) {
+            let value_start = pos + marker.len();
+            out.push_str(&rest[..value_start]);
+            let value_len = rest[value_start..]
+                .find(|c: char| c.is_control() || terminators.contains(&c))
+                .unwrap_or(rest.len() - value_start);
+            if value_len > 0 {
+                out.push_str(MASK);
+            }
+            rest = &rest[value_start + value_len..];
+        }
+        out.push_str(rest);
+        text = Cow::Owned(out);
+    }
+    text
+}
+
+/// 日志输出前先脱敏，包装任意 `MakeWriter`
+pub struct Redacting<M>(M);
+
+impl<M> Redacting<M> {
+    pub fn new(make_writer: M) -> Self {
+        Redacting(make_writer)
+    }
+}
+
+impl<'a, M: MakeWriter<'a>> MakeWriter<'a> for Redacting<M> {
+    type Writer = RedactingWriter<M::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactingWriter(self.0.make_writer())
+    }
+}
+
+/// 每次写入的是一条完整的日志，整体脱敏后再写入
+pub struct RedactingWriter<W>(W);
+
+impl<W: Write> Write for RedactingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let text = String::from_utf8_lossy(buf);
+        self.0.write_all(redact(&text).as_bytes())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn secret_is_masked() {
+        let secret = Secret("hunter2-secret".to_string());
+        assert_eq!(format!("{}", secret), MASK);
+        assert_eq!(format!("{:?}", secret), MASK);
+        assert_eq!(format!("{:?}", Some(&secret)), format!("Some({})", MASK));
+        assert_eq!(secret.expose(), "hunter2-secret");
+
+        let key = Secret([1u8, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(format!("{:?}", key), MASK);
+    }
+
+    #[test]
+    fn markers_are_redacted() {
+        let text = concat!(
+            "协议内容 plaa://QUJDREVGR0hJSktM&x=1 ",
+            "启动参数 -handle 1234:5678 -lang zh ",
+            "<password>hunter2</password> ",
+            r#"{"u":"player","p":"hunter2"}"#,
+        );
+        let redacted = redact(text);
+        assert!(!redacted.contains("QUJDREVGR0hJSktM"));
+        assert!(!redacted.contains("1234:5678"));
+        assert!(!redacted.contains("hunter2"));
+        assert!(redacted.contains("&x=1"));
+        assert!(redacted.contains("-lang zh"));
+        assert!(redacted.contains("player"));
+        assert_eq!(redact("普通日志"), "普通日志");
+    }
+
+    /// 收集日志输出的缓冲区
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn tracing_output_is_redacted() {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_ansi(false)
+            .with_writer(Redacting::new(move || writer.clone()))
+            .finish();
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(
+                "启动 plaa://QUJDREVGR0hJSktM -handle 1234:5678 <password>hunter2</password> {}",
+                r#"{"p":"hunter2"}"#
+            );
+        });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert!(output.contains("启动 plaa://"));
+        assert!(output.contains(MASK));
+        assert!(!output.contains("QUJDREVGR0hJSktM"));
+        assert!(!output.contains("1234:5678"));
+        assert!(!output.contains("hunter2"));
+    }
+}
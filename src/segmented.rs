@@ -0,0 +1,294 @@
+use std::cell::{Cell, RefCell};
+use std::io::{self, SeekFrom};
+use std::path::Path;
+
+use futures::future::join_all;
+use futures::StreamExt;
+use reqwest::header::{ACCEPT_RANGES, IF_RANGE, RANGE};
+use reqwest::StatusCode;
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::watch;
+use tracing::{debug, info};
+
+use crate::download::Compression;
+use crate::download_control::Cancelled;
+use crate::events::{Event, EventBus};
+use crate::progress::Tracker;
+use crate::{disk_space, download_control, download_meta, http, rate_limit};
+
+/// 每个分段至少的大小，文件太小时不分段
+const MIN_SEGMENT_SIZE: u64 = 4 * 1024 * 1024;
+
+/// 支持分段下载的服务器文件
+pub struct Remote {
+    pub length: u64,
+    /// 服务器版本标识，分段请求和之后的续传用 `If-Range` 确认文件没有变化
+    pub validator: Option<String>,
+}
+
+/// 检查服务器是否支持分段下载，支持时返回文件大小和版本标识
+pub async fn probe(url: &str) -> Option<Remote> {
+    let response = http::client().head(url).send().await.ok()?;
+    let response = response.error_for_status().ok()?;
+    let ranges = response
+        .headers()
+        .get(ACCEPT_RANGES)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.eq_ignore_ascii_case("bytes"));
+    let length = response.content_length().filter(|l| *l > 0)?;
+
+    // 压缩数据需要顺序解压，不能分段
+    if !ranges || Compression::detect(url, response.headers()) != Compression::None {
+        return None;
+    }
+    Some(Remote {
+        length,
+        validator: download_meta::validator(response.headers()),
+    })
+}
+
+/// 根据连接数上限和文件大小计算分段数量
+pub fn segment_count(length: u64, connections: u32) -> u64 {
+    (length / MIN_SEGMENT_SIZE).clamp(1, connections.max(1) as u64)
+}
+
+/// 一个分段，`position` 为下一个要写入的位置，超过 `end` 时下载完成
+struct Segment {
+    position: Cell<u64>,
+    end: u64,
+}
+
+impl Segment {
+    fn is_done(&self) -> bool {
+        self.position.get() > self.end
+    }
+}
+
+/// 下载一个分段，其他分段失败时停止。出错时不在这里重试，由 `download::fetch` 统一重试
+async fn fetch_segment(
+    url: &str,
+    path: &Path,
+    segment: &Segment,
+    validator: Option<&str>,
+    tracker: &RefCell<Tracker>,
+    events: &EventBus,
+    mut stopped: watch::Receiver<bool>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let control = download_control::controller();
+    let (start, end) = (segment.position.get(), segment.end);
+
+    let mut request = http::client()
+        .get(url)
+        .header(RANGE, format!("bytes={}-{}", start, end));
+    // 文件在下载过程中变化时服务器返回完整文件，不能与已下载的分段混用
+    if let Some(validator) = validator {
+        request = request.header(IF_RANGE, validator);
+    }
+    let response = request.send().await?.error_for_status()?;
+    if response.status() != StatusCode::PARTIAL_CONTENT {
+        return Err("服务器未按分段返回数据".into());
+    }
+
+    let mut file = OpenOptions::new().write(true).open(path).await?;
+    file.seek(SeekFrom::Start(start)).await?;
+
+    let mut stream = response.bytes_stream();
+    let result: Result<(), Box<dyn std::error::Error>> = async {
+        while let Some(chunk) = tokio::select! {
+            chunk = stream.next() => chunk,
+            _ = control.wait_cancel() => return Err(Cancelled.into()),
+            _ = stopped.wait_for(|stop| *stop) => return Ok(()),
+        } {
+            let chunk = chunk?;
+            control.checkpoint().await?;
+            tokio::select! {
+                _ = rate_limit::limiter().acquire(chunk.len() as u64) => {}
+                _ = control.wait_cancel() => return Err(Cancelled.into()),
+            }
+
+            // 只写入本段范围内的数据
+            let remaining = (end + 1 - segment.position.get()) as usize;
+            let data = &chunk[..chunk.len().min(remaining)];
+            file.write_all(data).await?;
+            segment
+                .position
+                .set(segment.position.get() + data.len() as u64);
+
+            let report = tracker.borrow_mut().advance(data.len() as u64);
+            if let Some(p) = report {
+                events.publish(Event::Progress(p));
+            }
+            if segment.is_done() {
+                break;
+            }
+        }
+        if !segment.is_done() {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        Ok(())
+    }
+    .await;
+    // 失败时也要刷新到磁盘，保留的部分才完整
+    file.flush().await?;
+    result
+}
+
+/// 把文件分成多个区间并发下载，写入同一个文件的对应位置。
+/// 取消并保留或网络临时错误时，保留从头开始连续下载完成的部分，由调用方单线程续传；
+/// 其他错误删除不完整的文件。
+pub async fn fetch(
+    url: &str,
+    filename: &str,
+    offset: u64,
+    total: u64,
+    remote: &Remote,
+    connections: u32,
+    events: &EventBus,
+) -> Result<u64, Box<dyn std::error::Error>> {
+    let path = Path::new(filename);
+    let length = remote.length;
+    let count = segment_count(length, connections);
+    let size = length.div_ceil(count);
+    info!("分段下载 {} 共 {} bytes，{} 个连接", url, length, count);
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    disk_space::check(path, length)?;
+    File::create(path).await?.set_len(length).await?;
+    download_meta::store_partial(filename, url, remote.validator.clone());
+
+    let total = if total > 0 { total } else { length };
+    let tracker = RefCell::new(Tracker::new(offset, Some(total)));
+
+    let segments: Vec<Segment> = (0..count)
+        .map(|i| {
+            let start = i * size;
+            let end = ((i + 1) * size).min(length) - 1;
+            debug!("分段 {} {}-{}", i, start, end);
+            Segment {
+                position: Cell::new(start),
+                end,
+            }
+        })
+        .collect();
+
+    // 一个分段失败时通知其他分段停止
+    let (stop, stopped) = watch::channel(false);
+    let validator = remote.validator.as_deref();
+    let results = join_all(segments.iter().map(|segment| {
+        let (stop, stopped, tracker) = (&stop, stopped.clone(), &tracker);
+        async move {
+            let res = fetch_segment(url, path, segment, validator, tracker, events, stopped).await;
+            if res.is_err() {
+                stop.send_replace(true);
+            }
+            res
+        }
+    }))
+    .await;
+
+    let Some(e) = results.into_iter().find_map(Result::err) else {
+        download_meta::forget(filename);
+        return Ok(length);
+    };
+
+    let keep = if download_control::is_cancelled(e.as_ref()) {
+        download_control::controller().cancelled() == Some(false)
+    } else {
+        http::is_transient(e.as_ref())
+    };
+    let completed = completed_prefix(&segments);
+    if keep && completed > 0 {
+        OpenOptions::new()
+            .write(true)
+            .open(path)
+            .await?
+            .set_len(completed)
+            .await?;
+        info!("保留已下载的部分 {} bytes {:?}", completed, path);
+    } else {
+        let _ = fs::remove_file(path).await;
+        download_meta::forget(filename);
+    }
+    Err(e)
+}
+
+/// 从文件开头连续下载完成的字节数
+fn completed_prefix(segments: &[Segment]) -> u64 {
+    let mut completed = 0;
+    for segment in segments {
+        completed = segment.position.get();
+        if !segment.is_done() {
+            break;
+        }
+    }
+    completed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::stub::{response, serve_with};
+    use crate::test_util::temp_file;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn keep_completed_prefix_on_network_error() {
+        let (length, size) = (2 * MIN_SEGMENT_SIZE, MIN_SEGMENT_SIZE as usize);
+        let data: Vec<u8> = (0..length).map(|i| (i % 251) as u8).collect();
+        let body = Arc::new(data.clone());
+        let stub = serve_with(2, move |request| {
+            if request.contains("range: bytes=0-") {
+                response("206 Partial Content", &[], &body[..size])
+            } else {
+                // 第一段完成后，第二段只发送一部分就断开
+                std::thread::sleep(Duration::from_millis(200));
+                let length = format!("Content-Length: {}", size);
+                response("206 Partial Content", &[&length], &body[size..size + 1000])
+            }
+        });
+        let remote = Remote {
+            length,
+            validator: Some("\"v1\"".to_string()),
+        };
+        let filename = temp_file("segmented-keep");
+
+        let e = fetch(&stub.url, &filename, 0, 0, &remote, 2, &EventBus::new())
+            .await
+            .unwrap_err();
+        assert!(http::is_transient(e.as_ref()));
+        // 第一段和第二段已收到的部分连续
+        let kept = std::fs::read(&filename).unwrap();
+        assert_eq!(kept.len(), size + 1000);
+        assert!(kept == data[..size + 1000]);
+        assert!(stub.requests()[0].contains("if-range: \"v1\""));
+        assert_eq!(
+            download_meta::if_range(&filename, &stub.url).as_deref(),
+            Some("\"v1\"")
+        );
+        // 换到其他镜像续传时不发送 If-Range
+        assert_eq!(download_meta::if_range(&filename, "http://other/"), None);
+
+        download_meta::forget(&filename);
+        let _ = std::fs::remove_file(&filename);
+    }
+
+    #[tokio::test]
+    async fn delete_on_permanent_error() {
+        let stub = serve_with(2, |_| response("200 OK", &[], b""));
+        let remote = Remote {
+            length: 2 * MIN_SEGMENT_SIZE,
+            validator: None,
+        };
+        let filename = temp_file("segmented-delete");
+
+        let e = fetch(&stub.url, &filename, 0, 0, &remote, 2, &EventBus::new())
+            .await
+            .unwrap_err();
+        assert!(!http::is_transient(e.as_ref()));
+        assert!(!Path::new(&filename).exists());
+    }
+}
@@ -0,0 +1,64 @@
+//! 更新清单的 Ed25519 签名，启动器和 `sign-manifest` 工具共用。
+//! 启动器只验证签名，生成密钥和签名只有 `sign-manifest` 使用
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+
+/// 签名文件名：清单文件名加上这个后缀，内容为 Base64 编码的签名
+pub const SUFFIX: &str = ".sig";
+
+fn key_bytes(hex_key: &str) -> Result<[u8; 32], Box<dyn std::error::Error>> {
+    let bytes = hex::decode(hex_key.trim())?;
+    bytes.try_into().map_err(|_| "密钥长度应为 32 字节".into())
+}
+
+/// 由十六进制私钥生成十六进制公钥
+#[cfg_attr(not(test), allow(dead_code))]
+pub fn public_key(secret_hex: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let signing = SigningKey::from_bytes(&key_bytes(secret_hex)?);
+    Ok(hex::encode(signing.verifying_key().as_bytes()))
+}
+
+/// 用十六进制私钥签名，返回 Base64 编码的签名
+#[cfg_attr(not(test), allow(dead_code))]
+pub fn sign(secret_hex: &str, data: &[u8]) -> Result<String, Box<dyn std::error::Error>> {
+    let signing = SigningKey::from_bytes(&key_bytes(secret_hex)?);
+    Ok(STANDARD.encode(signing.sign(data).to_bytes()))
+}
+
+/// 用十六进制公钥验证 Base64 编码的签名
+pub fn verify(
+    public_hex: &str,
+    data: &[u8],
+    signature: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let key = VerifyingKey::from_bytes(&key_bytes(public_hex)?)?;
+    let signature = Signature::from_slice(&STANDARD.decode(signature.trim())?)?;
+    key.verify(data, &signature)
+        .map_err(|_| "清单签名无效".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60";
+
+    #[test]
+    fn sign_and_verify() {
+        let public = public_key(SECRET).unwrap();
+        let data = br#"{"files":[]}"#;
+        let signature = sign(SECRET, data).unwrap();
+        assert!(verify(&public, data, &signature).is_ok());
+
+        // 内容被修改
+        assert!(verify(&public, br#"{"files":[{}]}"#, &signature).is_err());
+        // 其他密钥
+        let other = public_key(&"11".repeat(32)).unwrap();
+        assert!(verify(&other, data, &signature).is_err());
+        // 签名或密钥格式错误
+        assert!(verify(&public, data, "not base64").is_err());
+        assert!(verify("abcd", data, &signature).is_err());
+    }
+}
@@ -1,3 +1,4 @@
+use crate::trusted_domain;
 use std::env;
 use tracing::{error, info};
 use windows::core::{Interface, HSTRING};
@@ -8,6 +9,9 @@ use windows::Win32::System::Com::{
 use windows::Win32::UI::Shell::{IShellLinkW, ShellLink};
 
 pub async fn handle(domain: &str) -> Result<(), Box<dyn std::error::Error>> {
+    // 快捷方式只指向可信域名
+    trusted_domain::check(domain)?;
+
     let homepath = std::env::var("USERPROFILE")
         .or_else(|_| std::env::var("HOMEPATH"))
         .expect("获取用户环境失败 HOMEPATH");
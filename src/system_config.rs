@@ -1,9 +1,18 @@
 use std::fs;
 use std::fs::File;
 use std::io::Write;
-use std::path::Path;
+use std::path::PathBuf;
 use tracing::{info, warn};
 
+/// 游戏配置文件 `Documents\AAEmu\system.cfg`
+pub fn file_path() -> PathBuf {
+    let homepath = std::env::var("USERPROFILE").or_else(|_| {
+        std::env::var("HOMEPATH")
+    }).expect("获取用户环境失败 HOMEPATH");
+
+    PathBuf::from(format!("{}\\Documents\\AAEmu\\system.cfg", homepath))
+}
+
 pub async fn update() -> Result<(), Box<dyn std::error::Error>> {
     let content = r#"-- [SYSTEM CONFIGURATION]
 -- WARNING! THIS FILE IS GENERATED BY THE SYSTEM! EDITING IS NOT RECOMMENDED!
@@ -16,16 +25,8 @@ r_vsync = 0
 locale = zh_cn
     "#;
 
-    let homepath = std::env::var("USERPROFILE").or_else(|_| {
-        std::env::var("HOMEPATH")
-    }).expect("获取用户环境失败 HOMEPATH");
-
-
-    let file_name = "system.cfg";
-
-    // 创建一个包含目录和文件名的完整路径
-    let file_path = format!("{}\\Documents\\AAEmu\\{}", homepath, file_name);
-    let file_path = Path::new(&file_path);
+    let file_path = file_path();
+    let file_path = file_path.as_path();
 
     // 检查文件是否存在
     if !file_path.exists() {
@@ -1,28 +1,37 @@
-use crate::{MainWindow, State, Task};
-use tokio::sync::mpsc::Receiver;
-use tracing::{debug, warn};
+use crate::events::{Event, Subscriber};
+use crate::{MainWindow, MessageActions, State};
+use tracing::debug;
 
-pub async fn handle(rx: &mut Receiver<Task>, app: &MainWindow) {
+/// 界面订阅者，事件总线关闭后结束
+pub async fn handle(mut events: Subscriber, app: &MainWindow) {
     debug!("等待接收任务");
-    loop {
-        match rx.recv().await {
-            None => {
-                warn!("想终止任务？");
-            }
-            Some(msg) => match msg {
-                Task::Progress(percentage) => {
-                    debug!("升级进度:{}", percentage);
-                    app.invoke_changeProgres(percentage as f32);
-                    if percentage >= 100f64 {
-                        app.invoke_changeState(State::Ready);
+    while let Some(event) = events.recv().await {
+        match event {
+            Event::Progress(progress) => {
+                debug!("升级进度:{}", progress);
+                match progress.percentage() {
+                    Some(percentage) => {
+                        app.invoke_changeProgres(percentage as f32);
+                        app.invoke_changeProgressText(progress.to_string().into(), false);
                     }
-                    debug!("进度条更新完成");
-                }
-                Task::Message(title, content, action) => {
-                    debug!("发送消息通知");
-                    app.invoke_message(title.into(), content.into(), action);
+                    None => app.invoke_changeProgressText(progress.to_string().into(), true),
                 }
-            },
+                debug!("进度条更新完成");
+            }
+            Event::Finished => {
+                app.invoke_changeProgres(100f32);
+                app.invoke_changeProgressText("".into(), false);
+                app.invoke_changeState(State::Ready);
+            }
+            Event::Message(title, content, action) => {
+                debug!("发送消息通知");
+                app.invoke_message(title.into(), content.into(), action);
+            }
+            Event::Error(e) => {
+                app.invoke_message("启动器".into(), e.into(), MessageActions::None);
+            }
+            Event::Stage(_) | Event::GameStarted(_) | Event::GameExited(_) => {}
         }
     }
+    debug!("事件总线已关闭，停止接收任务");
 }
@@ -0,0 +1,31 @@
+//! 测试用的临时路径，按名称和进程区分，互不干扰
+
+use std::fs;
+use std::path::PathBuf;
+
+fn path(name: &str) -> PathBuf {
+    // 测试线程名形如 `download::tests::resume`，Windows 路径中不能出现冒号
+    let name = name.replace("::", "-");
+    std::env::temp_dir().join(format!("plaa-{}-{}", name, std::process::id()))
+}
+
+/// 临时文件路径，已存在的文件会被删除
+pub fn temp_file(name: &str) -> String {
+    let path = path(name);
+    let _ = fs::remove_file(&path);
+    path.to_string_lossy().into_owned()
+}
+
+/// 空的临时目录
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = path(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// 测试中的数据目录，每个测试线程各用一个，不读写真实的配置和下载记录
+pub fn data_dir() -> PathBuf {
+    let thread = std::thread::current();
+    path(&format!("data-{}", thread.name().unwrap_or("main")))
+}
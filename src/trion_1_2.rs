@@ -4,7 +4,7 @@ use std::mem::size_of;
 use std::os::windows::process::CommandExt;
 use std::process::Stdio;
 use std::time::Duration;
-use std::{env, ptr};
+use std::ptr;
 use tracing::{debug, error, info};
 use windows::core::w;
 use windows::Win32::Foundation::{HANDLE, INVALID_HANDLE_VALUE};
@@ -14,6 +14,10 @@ use windows::Win32::System::Memory::{
 };
 use windows::Win32::System::Threading::{CreateEventW, DETACHED_PROCESS};
 
+use crate::events::{Event, EventBus};
+use crate::helper;
+use crate::redact;
+use zeroize::Zeroizing;
 use crate::protocol::AuthToken;
 
 const ARCHEAGE: &str = "\\archeage.exe";
@@ -23,16 +27,13 @@ pub fn init_ticket(
     username: &str,
     password: &str,
 ) -> Result<(usize, usize), Box<dyn std::error::Error>> {
-    let mut encryption_key = [0u8; 8];
+    let mut encryption_key = Zeroizing::new([0u8; 8]);
 
     // 创建随机数生成器
     let mut rng = rand::rng();
 
     // 填充字节数组
-    rng.fill(&mut encryption_key);
-
-    // 打印生成的随机字节数组（这里将其转为十六进制字符串以便查看）
-    info!("随机Key: {:?}", hex::encode(encryption_key));
+    rng.fill(&mut *encryption_key);
 
     // Step 1: Set up SECURITY_ATTRIBUTES for handle inheritance
     let mut sa = SECURITY_ATTRIBUTES {
@@ -42,9 +43,20 @@ pub fn init_ticket(
     };
 
     // Step 2: Define the maximum size for the file mapping
-    let mut ticket_data = format!("TFIRdGVzdA==\n<?xml version=\"1.0\" encoding=\"UTF - 8\" standalone=\"yes\"?><authTicket version=\"1.2\"><storeToken>1</storeToken><client>PLAA</client><username>{}</username><password>{}</password></authTicket>", username, password).into_bytes(); // your encrypted data
+    // 明文票据包含密码：预先分配好容量避免扩容时留下副本，释放时清零
+    let parts = [
+        "TFIRdGVzdA==\n<?xml version=\"1.0\" encoding=\"UTF - 8\" standalone=\"yes\"?><authTicket version=\"1.2\"><storeToken>1</storeToken><client>PLAA</client><username>",
+        username,
+        "</username><password>",
+        password,
+        "</password></authTicket>",
+    ];
+    let mut ticket_data = Zeroizing::new(Vec::with_capacity(parts.iter().map(|p| p.len()).sum()));
+    for part in parts {
+        ticket_data.extend_from_slice(part.as_bytes());
+    }
 
-    let mut rc4 = Rc4::new(&encryption_key.into());
+    let mut rc4 = Rc4::new(&(*encryption_key).into());
 
     rc4.apply_keystream(&mut ticket_data);
 
@@ -88,7 +100,7 @@ pub fn init_ticket(
     unsafe {
         let dest =
             std::slice::from_raw_parts_mut(file_map_view.Value as *mut u8, max_map_size as usize);
-        dest[..8].copy_from_slice(&encryption_key);
+        dest[..8].copy_from_slice(&*encryption_key);
 
         let ticket_len: u32 = ticket_data.len() as u32;
         let tkl_byte = ticket_len.to_le_bytes();
@@ -110,66 +122,47 @@ pub fn init_ticket(
     Ok((file_map_handle.0 as usize, event_handle.0 as usize))
 }
 
-pub(crate) async fn launch(auth_token: &AuthToken) {
-    let (p0, p1) = init_ticket(&auth_token.username, &auth_token.password).expect("初始化令牌失败");
+/// 令牌交给客户端后立即释放，密码随之清零
+pub(crate) async fn launch(auth_token: AuthToken, events: &EventBus) {
+    let (p0, p1) = init_ticket(&auth_token.username, auth_token.password.expose()).expect("初始化令牌失败");
 
     let handle_args = format!(
         "-t +auth_ip {:} -auth_port {:} -handle {:08X}:{:08X} -lang zh_cn +acpxmk",
         auth_token.server, auth_token.port, p0, p1
     );
 
-    debug!("{:?}", handle_args);
+    debug!("启动参数 {}", redact::redact(&handle_args));
 
-    let root_path = env::current_exe()
-        .expect("获取当前路径失败")
-        .parent()
-        .expect("获取父级目录")
-        .to_str()
-        .expect("转换为字符串失败")
-        .to_string();
+    let root_path = helper::game_root();
 
-    let exe_path = format!("{}{}{}", root_path, SUB_DIR, ARCHEAGE);
+    let exe_path = format!("{}{}{}", root_path.display(), SUB_DIR, ARCHEAGE);
 
-    let _result = std::process::Command::new(exe_path)
+    let result = std::process::Command::new(exe_path)
+        .current_dir(&root_path)
         .raw_arg(handle_args)
         .stdin(Stdio::null()) // 分离标准输入
         .stdout(Stdio::null()) // 分离标准输出
         .stderr(Stdio::null()) // 分离标准错误;
         .creation_flags(DETACHED_PROCESS.0) // 设置分离进程标志
-        // 启动程序并等待它完成
-        .spawn()
-        .expect("Failed to start process");
-
-    tokio::time::sleep(Duration::from_secs(5)).await;
-    //
-    //
-    // match _result.try_wait() {
-    //     Ok(status) => {
-    //         info!("{:?}", status);
-    //     }
-    //     Err(_) => {
-    //         return;
-    //     }
-    // };
-
-    // // 检查程序的退出状态
-    // if status.success() {
-    //     info!("程序启动成功");
-    // } else {
-    //     error!("程序启动失败: {:?}", status);
-    // }
-}
-
-pub async fn handle_bin_check() -> bool {
-    let root_path = env::current_exe()
-        .expect("获取当前路径失败")
-        .parent()
-        .expect("获取父级目录")
-        .to_str()
-        .expect("转换为字符串失败")
-        .to_string();
+        .spawn();
+    drop(auth_token);
+
+    let mut child = match result {
+        Ok(child) => child,
+        Err(e) => {
+            error!("启动游戏失败 {:?}", e);
+            events.publish(Event::Error(format!("启动游戏失败: {}", e)));
+            return;
+        }
+    };
+    events.publish(Event::GameStarted(child.id()));
 
-    let exe_path = format!("{}{}{}", root_path, SUB_DIR, ARCHEAGE);
+    // 在单独的线程中等待游戏退出，不占用异步运行时
+    let events = events.clone();
+    std::thread::spawn(move || {
+        let code = child.wait().ok().and_then(|status| status.code());
+        events.publish(Event::GameExited(code));
+    });
 
-    std::path::Path::exists(exe_path.as_ref())
+    tokio::time::sleep(Duration::from_secs(5)).await;
 }
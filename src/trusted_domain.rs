@@ -0,0 +1,61 @@
+use std::fmt;
+
+use reqwest::Url;
+
+use crate::launcher_config;
+
+/// 内置的可信域名，子域名同样可信
+const BUILTIN: &[&str] = &["plaa.top"];
+
+/// 令牌中的域名未通过检查
+#[derive(Debug)]
+pub enum UntrustedDomain {
+    Invalid(String),
+    NotHttps(String),
+    Unknown(String),
+}
+
+impl fmt::Display for UntrustedDomain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UntrustedDomain::Invalid(url) => write!(f, "地址无效：{}", url),
+            UntrustedDomain::NotHttps(url) => write!(f, "必须使用 HTTPS：{}", url),
+            UntrustedDomain::Unknown(host) => write!(f, "未知域名：{}", host),
+        }
+    }
+}
+
+impl std::error::Error for UntrustedDomain {}
+
+/// 配置中的域名可以带 `https://` 前缀或结尾的 `/`
+fn normalize(domain: &str) -> String {
+    let domain = domain.trim();
+    let domain = domain.strip_prefix("https://").unwrap_or(domain);
+    domain.trim_end_matches('/').to_ascii_lowercase()
+}
+
+/// 检查令牌 `d` 字段：必须是 HTTPS，且主机为内置或配置 `trusted_domains` 中的域名
+pub fn check(url: &str) -> Result<(), UntrustedDomain> {
+    let parsed = Url::parse(url).map_err(|_| UntrustedDomain::Invalid(url.to_string()))?;
+    if parsed.scheme() != "https" {
+        return Err(UntrustedDomain::NotHttps(url.to_string()));
+    }
+    let host = parsed
+        .host_str()
+        .ok_or_else(|| UntrustedDomain::Invalid(url.to_string()))?
+        .to_ascii_lowercase();
+
+    let configured = launcher_config::load().trusted_domains;
+    let trusted = BUILTIN
+        .iter()
+        .map(|d| d.to_string())
+        .chain(configured.iter().map(|d| normalize(d)))
+        .filter(|d| !d.is_empty())
+        .any(|d| host == d || host.ends_with(&format!(".{}", d)));
+
+    if trusted {
+        Ok(())
+    } else {
+        Err(UntrustedDomain::Unknown(host))
+    }
+}
@@ -1,44 +1,331 @@
-use std::sync::atomic::Ordering;
+use crate::events::EventBus;
+use crate::{
+    bug_report, business_logic, client_repair, crash_report, diagnostics, download,
+    download_control, launcher_config, logging, protocol, rate_limit, trusted_domain, web_site,
+    MainWindow, MessageActions, State, WEBSITE_URL,
+};
 use slint::ComponentHandle;
-use crate::{business_logic, download, protocol, web_site, MainWindow, PAUSE_UPGRADE, WEBSITE_URL};
-use tracing::{debug, info};
+use tracing::{debug, error, info};
 
-pub(crate) fn create() -> Result<MainWindow, Box<dyn std::error::Error>> {
+pub(crate) fn create(events: &EventBus) -> Result<MainWindow, Box<dyn std::error::Error>> {
     let main_window = MainWindow::new()?;
 
+    // 结束事件循环，由 main 关闭事件总线后退出
     main_window.on_exit(|| {
-        std::process::exit(0);
+        let control = download_control::controller();
+        if !control.is_active() {
+            let _ = slint::quit_event_loop();
+            return;
+        }
+        // 等待下载任务保存已下载的部分后再退出
+        control.cancel(false);
+        let res = slint::spawn_local(async move {
+            control.wait_idle().await;
+            let _ = slint::quit_event_loop();
+        });
+        if let Err(e) = res {
+            error!("无法等待下载任务结束，直接退出 {:?}", e);
+            let _ = slint::quit_event_loop();
+        }
     });
 
-    main_window.on_start_game(|| {
+    let bus = events.clone();
+    main_window.on_start_game(move || {
         info!("开始游戏");
 
+        let events = bus.clone();
         slint::spawn_local(async move {
             let auth_token = protocol::handle().await.unwrap();
-            business_logic::handle_launch(&auth_token).await;
+            business_logic::handle_launch(auth_token, &events).await;
         })
         .expect("TODO: panic message");
     });
     main_window.on_open_website(|| {
         web_site::open_website(WEBSITE_URL).expect("TODO: panic message");
     });
-    main_window.on_upgrade(|| {
+    let weak = main_window.as_weak();
+    let bus = events.clone();
+    main_window.on_upgrade(move || {
         debug!("开始更新DB");
+        let weak = weak.clone();
+        let events = bus.clone();
         slint::spawn_local(async move {
             debug!("开始更新DB2");
 
-            download::start_download_db().await.unwrap();
+            if let Err(e) = download::start_download_db(&events).await {
+                if download_control::is_cancelled(e.as_ref()) {
+                    info!("更新DB已取消");
+                    if let Some(window) = weak.upgrade() {
+                        window.invoke_changeState(State::Upgrade);
+                    }
+                    return;
+                }
+                error!("更新DB失败 {:?}", e);
+                if let Some(window) = weak.upgrade() {
+                    window.invoke_changeState(State::Upgrade);
+                    window.invoke_message(
+                        "更新DB".into(),
+                        format!("更新失败: {}\n是否重试？", e).into(),
+                        MessageActions::RetryUpgrade,
+                    );
+                }
+            }
         })
         .expect("TODO: panic message");
     });
 
+    let weak = main_window.as_weak();
+    main_window.on_verify_db(move || {
+        info!("重新校验DB");
+        let weak = weak.clone();
+        let res = slint::spawn_local(async move {
+            let auth_token = protocol::handle().await.unwrap();
+            let ok = business_logic::handle_db_check(&auth_token, true).await;
+            let Some(window) = weak.upgrade() else {
+                return;
+            };
+            window.invoke_changeState(if ok { State::Ready } else { State::Upgrade });
+        });
+        if let Err(e) = res {
+            error!("无法启动DB校验 {:?}", e);
+        }
+    });
+
+    let weak = main_window.as_weak();
+    let bus = events.clone();
+    main_window.on_repair(move || {
+        info!("校验并修复客户端");
+        let weak = weak.clone();
+        let events = bus.clone();
+        let res = slint::spawn_local(async move {
+            let auth_token = protocol::handle().await.unwrap();
+            let res = client_repair::handle(&auth_token, &events).await;
+            let Some(window) = weak.upgrade() else {
+                return;
+            };
+            match res {
+                Ok(0) => window.invoke_message(
+                    "校验修复".into(),
+                    "客户端文件完整".into(),
+                    MessageActions::None,
+                ),
+                Ok(count) => window.invoke_message(
+                    "校验修复".into(),
+                    format!("已修复 {} 个文件", count).into(),
+                    MessageActions::None,
+                ),
+                Err(e) if download_control::is_cancelled(e.as_ref()) => {
+                    info!("校验修复已取消");
+                    window.invoke_changeState(State::Ready);
+                }
+                Err(e) => {
+                    error!("校验修复失败 {:?}", e);
+                    window.invoke_changeState(State::Ready);
+                    window.invoke_message(
+                        "校验修复".into(),
+                        format!("校验修复失败: {}", e).into(),
+                        MessageActions::None,
+                    );
+                }
+            }
+        });
+        if let Err(e) = res {
+            error!("无法启动校验修复 {:?}", e);
+        }
+    });
+
     main_window.on_pause_upgrade(|v: bool| {
-        slint::spawn_local(async move {
-            if let Some(ac) = PAUSE_UPGRADE.get() {
-                ac.store(v, Ordering::Relaxed);
+        let control = download_control::controller();
+        if v {
+            control.pause();
+        } else {
+            control.resume();
+        }
+    });
+
+    main_window.invoke_changeSpeedLimit(rate_limit::limiter().limit_kb() as i32);
+    main_window.on_set_speed_limit(|kb: i32| {
+        let kb = kb.max(0) as u64;
+        rate_limit::limiter().set_limit_kb(kb);
+        if let Err(e) = launcher_config::update(|config| config.speed_limit_kb = kb) {
+            error!("保存限速设置失败 {:?}", e);
+        }
+    });
+
+    main_window.on_cancel_upgrade(|| {
+        download_control::controller().cancel(true);
+    });
+
+    main_window.on_open_crash_report(|| {
+        if let Err(e) = crash_report::open() {
+            error!("打开崩溃报告失败 {:?}", e);
+        }
+    });
+
+    main_window.on_dismiss_crash_report(|| {
+        crash_report::acknowledge();
+    });
+
+    let weak = main_window.as_weak();
+    main_window.on_submit_crash_report(move || {
+        let weak = weak.clone();
+        let res = slint::spawn_local(async move {
+            // 没有可信令牌时提交到官网
+            let domain = match protocol::handle().await {
+                Ok(token) if trusted_domain::check(&token.domain).is_ok() => token.domain.clone(),
+                _ => WEBSITE_URL.to_string(),
+            };
+            let res = crash_report::submit(&domain).await;
+            let Some(window) = weak.upgrade() else {
+                return;
+            };
+            match res {
+                Ok(()) => window.invoke_message(
+                    "崩溃报告".into(),
+                    "已提交，感谢反馈".into(),
+                    MessageActions::None,
+                ),
+                Err(e) => {
+                    error!("提交崩溃报告失败 {:?}", e);
+                    window.invoke_message(
+                        "崩溃报告".into(),
+                        format!("提交失败: {}", e).into(),
+                        MessageActions::None,
+                    );
+                }
             }
-        })
-            .expect("TODO: panic message");
+        });
+        if let Err(e) = res {
+            error!("无法提交崩溃报告 {:?}", e);
+        }
+    });
+
+    main_window.on_open_logs(|| {
+        if let Err(e) = logging::open_dir() {
+            error!("打开日志目录失败 {:?}", e);
+        }
+    });
+
+    let weak = main_window.as_weak();
+    main_window.on_create_diagnostics(move || {
+        let weak = weak.clone();
+        let res = slint::spawn_local(async move {
+            let default_path = diagnostics::default_path();
+            let mut dialog = rfd::AsyncFileDialog::new()
+                .set_title("保存诊断报告")
+                .add_filter("zip", &["zip"]);
+            if let Some(name) = default_path.file_name() {
+                dialog = dialog.set_file_name(name.to_string_lossy());
+            }
+            let Some(file) = dialog.save_file().await else {
+                return;
+            };
+            let path = file.path().to_path_buf();
+
+            let expected_hash = protocol::handle()
+                .await
+                .ok()
+                .map(|auth_token| auth_token.db_hash.clone())
+                .filter(|hash| !hash.is_empty());
+            let res = tokio::task::spawn_blocking({
+                let path = path.clone();
+                move || {
+                    diagnostics::create(&path, expected_hash.as_deref()).map_err(|e| e.to_string())
+                }
+            })
+            .await
+            .unwrap_or_else(|e| Err(e.to_string()));
+
+            let Some(window) = weak.upgrade() else {
+                return;
+            };
+            match res {
+                Ok(()) => window.invoke_message(
+                    "诊断报告".into(),
+                    format!("已保存到 {}", path.display()).into(),
+                    MessageActions::None,
+                ),
+                Err(e) => {
+                    error!("生成诊断报告失败 {}", e);
+                    window.invoke_message(
+                        "诊断报告".into(),
+                        format!("生成失败: {}", e).into(),
+                        MessageActions::None,
+                    );
+                }
+            }
+        });
+        if let Err(e) = res {
+            error!("无法生成诊断报告 {:?}", e);
+        }
+    });
+
+    let weak = main_window.as_weak();
+    main_window.on_submit_bug_report(move |text| {
+        let weak = weak.clone();
+        let res = slint::spawn_local(async move {
+            let auth_token = protocol::handle().await.unwrap();
+            let expected_hash = Some(auth_token.db_hash.clone()).filter(|hash| !hash.is_empty());
+            let res = bug_report::submit(&auth_token.domain, &text, expected_hash).await;
+            drop(auth_token);
+            let Some(window) = weak.upgrade() else {
+                return;
+            };
+            match res {
+                Ok(()) => window.invoke_message(
+                    "问题反馈".into(),
+                    "已提交，感谢反馈".into(),
+                    MessageActions::None,
+                ),
+                Err(e) => {
+                    error!("提交问题反馈失败 {:?}", e);
+                    window.invoke_message(
+                        "问题反馈".into(),
+                        format!("提交失败: {}", e).into(),
+                        MessageActions::None,
+                    );
+                }
+            }
+        });
+        if let Err(e) = res {
+            error!("无法提交问题反馈 {:?}", e);
+        }
+    });
+
+    let weak = main_window.as_weak();
+    let bus = events.clone();
+    main_window.on_pick_game_dir(move || {
+        let weak = weak.clone();
+        let events = bus.clone();
+        let res = slint::spawn_local(async move {
+            let Some(window) = weak.upgrade() else {
+                return;
+            };
+            let Some(folder) = rfd::AsyncFileDialog::new()
+                .set_title("选择游戏目录")
+                .pick_folder()
+                .await
+            else {
+                window.invoke_message(
+                    "未检测到游戏".into(),
+                    "未选择游戏目录".into(),
+                    MessageActions::Exit,
+                );
+                return;
+            };
+
+            match business_logic::handle_pick_game_dir(folder.path()) {
+                Ok(_) => business_logic::handle(&window, &events).await,
+                Err(e) => window.invoke_message(
+                    "游戏目录无效".into(),
+                    e.into(),
+                    MessageActions::PickGameDir,
+                ),
+            }
+        });
+        if let Err(e) = res {
+            error!("无法打开游戏目录选择 {:?}", e);
+        }
     });
 
     main_window.show().unwrap();